@@ -0,0 +1 @@
+ALTER TABLE subscriptions ADD COLUMN proxy VARCHAR(4096); -- NULLABLE
@@ -0,0 +1 @@
+ALTER TABLE subscriptions ADD COLUMN credentials BLOB; -- NULLABLE, encrypted
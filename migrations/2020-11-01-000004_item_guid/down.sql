@@ -0,0 +1,2 @@
+DROP INDEX unique_subscription_guid;
+ALTER TABLE items DROP COLUMN guid;
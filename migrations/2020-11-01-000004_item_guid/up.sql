@@ -0,0 +1,8 @@
+ALTER TABLE items ADD COLUMN guid VARCHAR(4096) NOT NULL DEFAULT '';
+
+-- Existing items use their URL until their entry is seen again
+UPDATE items SET guid = url;
+UPDATE items SET guid = guid || '#' || id
+WHERE id NOT IN (SELECT MIN(id) FROM items GROUP BY subscription_id, guid);
+
+CREATE UNIQUE INDEX unique_subscription_guid ON items (subscription_id, guid);
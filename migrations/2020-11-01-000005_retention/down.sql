@@ -0,0 +1,5 @@
+DROP TABLE purged_items;
+ALTER TABLE subscriptions DROP COLUMN keep_read_days;
+ALTER TABLE subscriptions DROP COLUMN keep_max_items;
+ALTER TABLE categories DROP COLUMN keep_read_days;
+ALTER TABLE categories DROP COLUMN keep_max_items;
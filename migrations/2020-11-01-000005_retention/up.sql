@@ -0,0 +1,15 @@
+-- Retention overrides: NULL to inherit, 0 for no limit
+ALTER TABLE subscriptions ADD COLUMN keep_read_days INTEGER;
+ALTER TABLE subscriptions ADD COLUMN keep_max_items INTEGER;
+ALTER TABLE categories ADD COLUMN keep_read_days INTEGER;
+ALTER TABLE categories ADD COLUMN keep_max_items INTEGER;
+
+-- Entries whose item was purged, so they aren't stored again while still in
+-- their feed
+CREATE TABLE purged_items (
+    subscription_id INTEGER NOT NULL,
+    guid VARCHAR(4096) NOT NULL,
+
+    PRIMARY KEY(subscription_id, guid),
+    FOREIGN KEY(subscription_id) REFERENCES subscriptions(id)
+);
@@ -0,0 +1,26 @@
+CREATE VIRTUAL TABLE items_fts USING fts5(
+    title,
+    author,
+    content,
+    content='items',
+    content_rowid='id'
+);
+
+INSERT INTO items_fts (items_fts) VALUES ('rebuild');
+
+CREATE TRIGGER items_fts_insert AFTER INSERT ON items BEGIN
+    INSERT INTO items_fts (rowid, title, author, content)
+    VALUES (new.id, new.title, new.author, new.content);
+END;
+
+CREATE TRIGGER items_fts_delete AFTER DELETE ON items BEGIN
+    INSERT INTO items_fts (items_fts, rowid, title, author, content)
+    VALUES ('delete', old.id, old.title, old.author, old.content);
+END;
+
+CREATE TRIGGER items_fts_update AFTER UPDATE OF title, author, content ON items BEGIN
+    INSERT INTO items_fts (items_fts, rowid, title, author, content)
+    VALUES ('delete', old.id, old.title, old.author, old.content);
+    INSERT INTO items_fts (rowid, title, author, content)
+    VALUES (new.id, new.title, new.author, new.content);
+END;
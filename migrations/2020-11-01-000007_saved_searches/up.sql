@@ -0,0 +1,7 @@
+CREATE TABLE saved_searches (
+    id INTEGER PRIMARY KEY NOT NULL,
+    name VARCHAR(256) NOT NULL,
+    query VARCHAR NOT NULL,
+
+    CONSTRAINT unique_name UNIQUE (name)
+);
@@ -0,0 +1,2 @@
+DROP TABLE item_labels;
+DROP TABLE rules;
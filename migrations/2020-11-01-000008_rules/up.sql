@@ -0,0 +1,25 @@
+CREATE TABLE rules (
+    id INTEGER PRIMARY KEY NOT NULL,
+    name VARCHAR(256) NOT NULL,
+    field VARCHAR(16) NOT NULL,
+    pattern VARCHAR NOT NULL,
+    is_regex BOOLEAN NOT NULL,
+    -- Scope: a subscription, a category or everything when both are NULL
+    subscription_id INTEGER, -- NULLABLE
+    category VARCHAR(256), -- NULLABLE
+    action VARCHAR(16) NOT NULL,
+    label VARCHAR(256), -- NULLABLE
+
+    CONSTRAINT unique_name UNIQUE (name),
+    FOREIGN KEY(subscription_id) REFERENCES subscriptions(id)
+);
+
+CREATE TABLE item_labels (
+    item_id INTEGER NOT NULL,
+    name VARCHAR(256) NOT NULL,
+
+    PRIMARY KEY(item_id, name),
+    FOREIGN KEY(item_id) REFERENCES items(id)
+);
+
+CREATE INDEX item_labels_name ON item_labels (name);
@@ -0,0 +1,3 @@
+UPDATE items SET content = raw_content WHERE content = '';
+ALTER TABLE items DROP COLUMN content_base;
+ALTER TABLE items DROP COLUMN raw_content;
@@ -0,0 +1,8 @@
+-- Content as found in the feed, before sanitizing
+ALTER TABLE items ADD COLUMN raw_content VARCHAR NOT NULL DEFAULT '';
+-- xml:base of the content in the feed, relative URLs of the content are
+-- relative to it
+ALTER TABLE items ADD COLUMN content_base VARCHAR; -- NULLABLE
+
+-- Existing contents aren't sanitized: they are processed again on startup
+UPDATE items SET raw_content = content, content = '';
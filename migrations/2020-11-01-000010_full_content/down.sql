@@ -0,0 +1,3 @@
+ALTER TABLE subscriptions DROP COLUMN fetch_full_content;
+ALTER TABLE subscriptions DROP COLUMN content_selector;
+ALTER TABLE items DROP COLUMN full_content;
@@ -0,0 +1,5 @@
+ALTER TABLE subscriptions ADD COLUMN fetch_full_content BOOLEAN NOT NULL DEFAULT 0;
+ALTER TABLE subscriptions ADD COLUMN content_selector VARCHAR; -- NULLABLE
+
+-- Article extracted from the item's page, empty if extraction failed
+ALTER TABLE items ADD COLUMN full_content VARCHAR; -- NULLABLE
@@ -0,0 +1,13 @@
+CREATE TABLE enclosures (
+    id INTEGER PRIMARY KEY NOT NULL,
+    item_id INTEGER NOT NULL,
+    url VARCHAR NOT NULL,
+    mime_type VARCHAR(256), -- NULLABLE
+    length BIGINT, -- NULLABLE, in bytes
+    duration INTEGER, -- NULLABLE, in seconds
+    thumbnail_url VARCHAR, -- NULLABLE
+
+    FOREIGN KEY(item_id) REFERENCES items(id)
+);
+
+CREATE INDEX enclosures_item_id ON enclosures (item_id);
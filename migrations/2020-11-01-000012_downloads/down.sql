@@ -0,0 +1,5 @@
+ALTER TABLE subscriptions DROP COLUMN download_enclosures;
+ALTER TABLE subscriptions DROP COLUMN keep_downloads;
+ALTER TABLE enclosures DROP COLUMN download_status;
+ALTER TABLE enclosures DROP COLUMN download_attempts;
+ALTER TABLE enclosures DROP COLUMN download_size;
@@ -0,0 +1,8 @@
+ALTER TABLE subscriptions ADD COLUMN download_enclosures BOOLEAN NOT NULL DEFAULT 0;
+-- Number of most recent items whose enclosures are kept, NULL for no limit
+ALTER TABLE subscriptions ADD COLUMN keep_downloads INTEGER; -- NULLABLE
+
+-- NULL when not downloaded, else "downloaded", "failed" or "removed"
+ALTER TABLE enclosures ADD COLUMN download_status VARCHAR(16); -- NULLABLE
+ALTER TABLE enclosures ADD COLUMN download_attempts INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE enclosures ADD COLUMN download_size BIGINT; -- NULLABLE, in bytes
@@ -0,0 +1,10 @@
+CREATE TABLE favicons (
+    subscription_id INTEGER PRIMARY KEY NOT NULL,
+    -- Where the icon was found, all NULL if none was
+    url VARCHAR, -- NULLABLE
+    content_type VARCHAR(256), -- NULLABLE
+    data BLOB, -- NULLABLE
+    fetched_at TIMESTAMP NOT NULL,
+
+    FOREIGN KEY(subscription_id) REFERENCES subscriptions(id)
+);
@@ -0,0 +1,10 @@
+-- Categories given by feeds to their entries
+CREATE TABLE item_tags (
+    item_id INTEGER NOT NULL,
+    name VARCHAR(256) NOT NULL COLLATE NOCASE,
+
+    PRIMARY KEY(item_id, name),
+    FOREIGN KEY(item_id) REFERENCES items(id)
+);
+
+CREATE INDEX item_tags_name ON item_tags (name);
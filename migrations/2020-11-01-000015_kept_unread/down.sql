@@ -0,0 +1 @@
+ALTER TABLE items DROP COLUMN is_kept_unread;
@@ -0,0 +1,2 @@
+-- Items the user explicitly kept unread, which automatic actions leave alone
+ALTER TABLE items ADD COLUMN is_kept_unread BOOLEAN NOT NULL DEFAULT 0;
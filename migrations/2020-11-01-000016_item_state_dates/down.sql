@@ -0,0 +1,4 @@
+DROP INDEX items_starred_at;
+DROP INDEX items_read_at;
+ALTER TABLE items DROP COLUMN starred_at;
+ALTER TABLE items DROP COLUMN read_at;
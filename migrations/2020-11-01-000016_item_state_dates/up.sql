@@ -0,0 +1,6 @@
+-- When items were last marked read and starred, NULL for older items
+ALTER TABLE items ADD COLUMN read_at TIMESTAMP; -- NULLABLE
+ALTER TABLE items ADD COLUMN starred_at TIMESTAMP; -- NULLABLE
+
+CREATE INDEX items_read_at ON items (read_at);
+CREATE INDEX items_starred_at ON items (starred_at);
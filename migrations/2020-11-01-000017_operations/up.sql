@@ -0,0 +1,31 @@
+-- Changes of the state of items, which can be reverted
+CREATE TABLE operations (
+    id INTEGER PRIMARY KEY NOT NULL,
+    kind VARCHAR(32) NOT NULL,
+    created_at TIMESTAMP NOT NULL,
+    -- New state of the items, NULL if unchanged
+    is_read BOOLEAN, -- NULLABLE
+    is_starred BOOLEAN, -- NULLABLE
+    is_kept_unread BOOLEAN, -- NULLABLE
+    item_count INTEGER NOT NULL,
+    is_reverted BOOLEAN NOT NULL DEFAULT 0
+);
+
+CREATE INDEX operations_created_at ON operations (created_at);
+
+-- State of the items changed by an operation, before it
+CREATE TABLE operation_items (
+    operation_id INTEGER NOT NULL,
+    item_id INTEGER NOT NULL,
+    was_read BOOLEAN NOT NULL,
+    was_starred BOOLEAN NOT NULL,
+    was_kept_unread BOOLEAN NOT NULL,
+    read_at TIMESTAMP, -- NULLABLE
+    starred_at TIMESTAMP, -- NULLABLE
+
+    PRIMARY KEY(operation_id, item_id),
+    FOREIGN KEY(operation_id) REFERENCES operations(id),
+    FOREIGN KEY(item_id) REFERENCES items(id)
+);
+
+CREATE INDEX operation_items_item_id ON operation_items (item_id);
@@ -0,0 +1,13 @@
+DROP TRIGGER categories_change_delete;
+DROP TRIGGER categories_change_insert;
+DROP TRIGGER subscription_categories_change_delete;
+DROP TRIGGER subscription_categories_change_insert;
+DROP TRIGGER subscriptions_change_delete;
+DROP TRIGGER subscriptions_change_update;
+DROP TRIGGER subscriptions_change_insert;
+DROP TRIGGER item_labels_change_delete;
+DROP TRIGGER item_labels_change_insert;
+DROP TRIGGER items_change_delete;
+DROP TRIGGER items_change_update;
+DROP TRIGGER items_change_insert;
+DROP TABLE changes;
@@ -0,0 +1,89 @@
+-- Latest change of each item, subscription and category, for syncing clients.
+-- Rows are replaced on each change so change_seq always increases.
+CREATE TABLE changes (
+    change_seq INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    kind VARCHAR(16) NOT NULL, -- item, subscription or category
+    object_id INTEGER NOT NULL,
+    name VARCHAR(256), -- NULLABLE, of categories, which are identified by name
+    is_deleted BOOLEAN NOT NULL DEFAULT 0,
+
+    CONSTRAINT unique_object UNIQUE (kind, object_id)
+);
+
+INSERT INTO changes (kind, object_id) SELECT 'subscription', id FROM subscriptions;
+INSERT INTO changes (kind, object_id, name) SELECT 'category', id, name FROM categories;
+INSERT INTO changes (kind, object_id) SELECT 'item', id FROM items ORDER BY published;
+
+CREATE TRIGGER items_change_insert AFTER INSERT ON items BEGIN
+    INSERT OR REPLACE INTO changes (kind, object_id)
+    VALUES ('item', new.id);
+END;
+
+CREATE TRIGGER items_change_update AFTER UPDATE ON items
+WHEN old.is_read IS NOT new.is_read
+    OR old.is_starred IS NOT new.is_starred
+    OR old.is_kept_unread IS NOT new.is_kept_unread
+    OR old.url IS NOT new.url
+    OR old.title IS NOT new.title
+    OR old.author IS NOT new.author
+    OR old.updated IS NOT new.updated
+    OR old.content IS NOT new.content
+    OR old.full_content IS NOT new.full_content
+BEGIN
+    INSERT OR REPLACE INTO changes (kind, object_id)
+    VALUES ('item', new.id);
+END;
+
+CREATE TRIGGER items_change_delete AFTER DELETE ON items BEGIN
+    INSERT OR REPLACE INTO changes (kind, object_id, is_deleted)
+    VALUES ('item', old.id, 1);
+END;
+
+CREATE TRIGGER item_labels_change_insert AFTER INSERT ON item_labels BEGIN
+    INSERT OR REPLACE INTO changes (kind, object_id)
+    VALUES ('item', new.item_id);
+END;
+
+CREATE TRIGGER item_labels_change_delete AFTER DELETE ON item_labels BEGIN
+    INSERT OR REPLACE INTO changes (kind, object_id)
+    VALUES ('item', old.item_id);
+END;
+
+CREATE TRIGGER subscriptions_change_insert AFTER INSERT ON subscriptions BEGIN
+    INSERT OR REPLACE INTO changes (kind, object_id)
+    VALUES ('subscription', new.id);
+END;
+
+CREATE TRIGGER subscriptions_change_update AFTER UPDATE ON subscriptions
+WHEN old.feed_url IS NOT new.feed_url
+    OR old.title IS NOT new.title
+    OR old.site_url IS NOT new.site_url
+BEGIN
+    INSERT OR REPLACE INTO changes (kind, object_id)
+    VALUES ('subscription', new.id);
+END;
+
+CREATE TRIGGER subscriptions_change_delete AFTER DELETE ON subscriptions BEGIN
+    INSERT OR REPLACE INTO changes (kind, object_id, is_deleted)
+    VALUES ('subscription', old.id, 1);
+END;
+
+CREATE TRIGGER subscription_categories_change_insert AFTER INSERT ON subscription_categories BEGIN
+    INSERT OR REPLACE INTO changes (kind, object_id)
+    VALUES ('subscription', new.subscription_id);
+END;
+
+CREATE TRIGGER subscription_categories_change_delete AFTER DELETE ON subscription_categories BEGIN
+    INSERT OR REPLACE INTO changes (kind, object_id)
+    VALUES ('subscription', old.subscription_id);
+END;
+
+CREATE TRIGGER categories_change_insert AFTER INSERT ON categories BEGIN
+    INSERT OR REPLACE INTO changes (kind, object_id, name)
+    VALUES ('category', new.id, new.name);
+END;
+
+CREATE TRIGGER categories_change_delete AFTER DELETE ON categories BEGIN
+    INSERT OR REPLACE INTO changes (kind, object_id, name, is_deleted)
+    VALUES ('category', old.id, old.name, 1);
+END;
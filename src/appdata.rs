@@ -1,18 +1,30 @@
+use crate::events::Events;
 use crate::feed_manager::FeedManager;
+use crate::image_proxy::ImageProxy;
 use crate::prelude::*;
 
 pub struct AppData {
     pub cfg: Config,
     pub db: db::Helper,
+    pub events: Events,
     pub feed_manager: FeedManager,
+    pub image_proxy: ImageProxy,
 }
 
 impl AppData {
-    pub fn new(cfg: Config, db: db::Helper, feed_manager: FeedManager) -> Self {
+    pub fn new(
+        cfg: Config,
+        db: db::Helper,
+        events: Events,
+        feed_manager: FeedManager,
+        image_proxy: ImageProxy,
+    ) -> Self {
         AppData {
             cfg,
             db,
+            events,
             feed_manager,
+            image_proxy,
         }
     }
 }
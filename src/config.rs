@@ -1,28 +1,123 @@
 use std::env::VarError;
 use std::fmt::{Debug, Display};
 use std::str::FromStr;
+use std::time::Duration;
 
 #[derive(Debug, Clone)]
 pub struct Config {
     pub http_host: String,
     pub http_port: u16,
+    /// URL under which this instance is reachable.
+    pub public_url: String,
 
     pub auth_username: String,
     pub auth_password: String,
 
     pub sqlite_db: String,
+
+    /// Key used to encrypt secrets stored in the database.
+    pub secret_key: Option<String>,
+
+    pub fetch_connect_timeout: Duration,
+    /// Total time allowed for a request.
+    pub fetch_timeout: Duration,
+    /// Maximum time to wait for the next part of a response.
+    pub fetch_read_timeout: Duration,
+    /// Maximum size of a fetched feed, in bytes.
+    pub fetch_max_size: usize,
+    pub user_agent: String,
+    /// Proxy used for all outbound requests, unless overridden.
+    pub proxy: Option<String>,
+    /// SOCKS5 proxy used for `.onion` feeds.
+    pub onion_proxy: Option<String>,
+
+    /// Hosts allowed as sources of iframes in item content.
+    pub iframe_hosts: Vec<String>,
+
+    /// Rewrite image URLs in item content to go through the image proxy.
+    pub image_proxy: bool,
+    /// Maximum size of a proxied image, in bytes.
+    pub image_proxy_max_size: usize,
+    /// Directory where proxied images are cached, no caching if unset.
+    pub image_cache_dir: Option<String>,
+    /// Maximum size of the image cache, in bytes.
+    pub image_cache_max_size: u64,
+
+    /// Directory where enclosures are downloaded, see `Downloader`.
+    pub download_dir: String,
+    /// Maximum total size of downloaded enclosures, in bytes.
+    pub download_quota: u64,
+    pub download_timeout: Duration,
+
+    /// Mark items unread again when their content is updated.
+    pub mark_updated_unread: bool,
+    /// How long changes of items' state can be reverted.
+    pub undo_window: Duration,
+
+    /// Default retention, see `RetentionPolicy`.
+    pub retention_read_days: Option<u32>,
+    pub retention_max_items: Option<u32>,
 }
 
 impl Config {
     pub fn from_env() -> Result<Self, Error> {
+        let http_host: String = Self::var("HOST")?;
+        let http_port: u16 = Self::var("PORT")?;
+        let public_url: String =
+            Self::var_or("PUBLIC_URL", format!("http://{}:{}", http_host, http_port))?;
+
+        let user_agent = Self::var_or(
+            "USER_AGENT",
+            format!(
+                "freader/{} (+{})",
+                env!("CARGO_PKG_VERSION"),
+                public_url.trim_end_matches('/')
+            ),
+        )?;
+
         Ok(Config {
-            http_host: Self::var("HOST")?,
-            http_port: Self::var("PORT")?,
+            http_host,
+            http_port,
+            public_url,
 
             auth_username: Self::var("USERNAME")?,
             auth_password: Self::var("PASSWORD")?,
 
             sqlite_db: Self::var_or("SQLITE_DB", "file:freader.sqlite")?,
+
+            secret_key: Self::var_opt("SECRET_KEY")?,
+
+            fetch_connect_timeout: Duration::from_secs(Self::var_or(
+                "FETCH_CONNECT_TIMEOUT",
+                10u64,
+            )?),
+            fetch_timeout: Duration::from_secs(Self::var_or("FETCH_TIMEOUT", 60u64)?),
+            fetch_read_timeout: Duration::from_secs(Self::var_or("FETCH_READ_TIMEOUT", 20u64)?),
+            fetch_max_size: Self::var_or("FETCH_MAX_SIZE", 20 * 1024 * 1024usize)?,
+            user_agent,
+            proxy: Self::var_opt("PROXY")?,
+            onion_proxy: Self::var_opt("ONION_PROXY")?,
+
+            iframe_hosts: Self::var_or::<_, String, _>("IFRAME_HOSTS", "")?
+                .split(',')
+                .map(|host| host.trim().to_owned())
+                .filter(|host| !host.is_empty())
+                .collect(),
+
+            image_proxy: Self::var_or("IMAGE_PROXY", false)?,
+            image_proxy_max_size: Self::var_or("IMAGE_PROXY_MAX_SIZE", 10 * 1024 * 1024usize)?,
+            image_cache_dir: Self::var_opt("IMAGE_CACHE_DIR")?,
+            image_cache_max_size: Self::var_or("IMAGE_CACHE_MAX_SIZE", 512 * 1024 * 1024u64)?,
+
+            download_dir: Self::var_or("DOWNLOAD_DIR", "downloads")?,
+            download_quota: Self::var_or("DOWNLOAD_QUOTA", 10 * 1024 * 1024 * 1024u64)?,
+            download_timeout: Duration::from_secs(Self::var_or("DOWNLOAD_TIMEOUT", 3600u64)?),
+
+            mark_updated_unread: Self::var_or("MARK_UPDATED_UNREAD", false)?,
+            undo_window: Duration::from_secs(Self::var_or("UNDO_WINDOW", 86400u64)?),
+
+            retention_read_days: Self::var_opt("RETENTION_READ_DAYS")?,
+            retention_max_items: Self::var_opt("RETENTION_MAX_ITEMS")?,
         })
     }
 
@@ -58,6 +153,19 @@ impl Config {
             res => res,
         }
     }
+
+    fn var_opt<K, V>(key: K) -> Result<Option<V>, Error>
+    where
+        K: Display,
+        V: FromStr,
+        V::Err: Display,
+    {
+        match Self::var(key) {
+            Ok(val) => Ok(Some(val)),
+            Err(Error::Missing(_)) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
 }
 
 
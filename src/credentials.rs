@@ -0,0 +1,256 @@
+use aes_gcm::aead::generic_array::GenericArray;
+use aes_gcm::aead::{Aead, NewAead};
+use aes_gcm::Aes256Gcm;
+use diesel::backend::Backend;
+use diesel::deserialize::{self, FromSql};
+use diesel::serialize::{self, ToSql};
+use diesel::sql_types::Binary;
+use hmac::Hmac;
+use rand::Rng;
+use reqwest::header::{self, HeaderName, HeaderValue};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+
+const NONCE_LEN: usize = 12;
+const SALT_LEN: usize = 16;
+
+/// First byte of encrypted credentials, the version of their format.
+const VERSION: u8 = 1;
+
+const PBKDF2_ROUNDS: u32 = 100_000;
+
+/// Secrets sent when fetching a private feed.
+///
+/// `Debug` is implemented manually so credentials never end up in logs.
+#[derive(Clone, Default, Deserialize, Serialize)]
+pub struct Credentials {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth: Option<Auth>,
+    /// Extra headers, as `(name, value)` pairs.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub headers: Vec<(String, String)>,
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub enum Auth {
+    Basic {
+        username: String,
+        password: Option<String>,
+    },
+    Bearer(String),
+    Cookie(String),
+}
+
+impl Credentials {
+    pub fn is_empty(&self) -> bool {
+        self.auth.is_none() && self.headers.is_empty()
+    }
+
+    /// Parse and add a `Name: value` header.
+    pub fn add_header(&mut self, line: &str) -> Result<(), &'static str> {
+        let mut parts = line.splitn(2, ':');
+        let name = parts.next().unwrap_or("").trim();
+        let value = parts.next().ok_or("Invalid header: missing ':'")?.trim();
+
+        HeaderName::from_bytes(name.as_bytes()).map_err(|_| "Invalid header name.")?;
+        HeaderValue::from_str(value).map_err(|_| "Invalid header value.")?;
+
+        self.headers.push((name.to_owned(), value.to_owned()));
+
+        Ok(())
+    }
+
+    /// Add the credentials to `request`.
+    pub fn apply(&self, mut request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        for (name, value) in &self.headers {
+            request = request.header(name.as_str(), value.as_str());
+        }
+
+        match &self.auth {
+            Some(Auth::Basic { username, password }) => {
+                request.basic_auth(username, password.as_ref())
+            }
+            Some(Auth::Bearer(token)) => request.bearer_auth(token),
+            Some(Auth::Cookie(cookie)) => request.header(header::COOKIE, cookie.as_str()),
+            None => request,
+        }
+    }
+}
+
+impl fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Credentials { .. }")
+    }
+}
+
+
+/// Credentials, as stored in the database.
+#[derive(Clone)]
+#[derive(AsExpression, FromSqlRow)]
+#[sql_type = "Binary"]
+pub struct EncryptedCredentials(Vec<u8>);
+
+impl fmt::Debug for EncryptedCredentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("EncryptedCredentials(..)")
+    }
+}
+
+impl<DB> FromSql<Binary, DB> for EncryptedCredentials
+where
+    DB: Backend,
+    Vec<u8>: FromSql<Binary, DB>,
+{
+    fn from_sql(value: Option<&DB::RawValue>) -> deserialize::Result<Self> {
+        let bytes = <Vec<u8> as FromSql<Binary, DB>>::from_sql(value)?;
+
+        Ok(EncryptedCredentials(bytes))
+    }
+}
+
+impl<DB> ToSql<Binary, DB> for EncryptedCredentials
+where
+    DB: Backend,
+    Vec<u8>: ToSql<Binary, DB>,
+{
+    fn to_sql<W: Write>(&self, out: &mut serialize::Output<W, DB>) -> serialize::Result {
+        self.0.to_sql(out)
+    }
+}
+
+
+/// Encrypts and decrypts credentials using keys derived from the secret key.
+///
+/// Each encryption uses a new salt, stored with the credentials. Derived keys
+/// are cached by salt since deriving them is purposely slow.
+#[derive(Clone)]
+pub struct Cipher {
+    secret_key: Vec<u8>,
+    keys: Arc<Mutex<HashMap<Vec<u8>, Aes256Gcm>>>,
+}
+
+impl Cipher {
+    pub fn new(secret_key: &str) -> Self {
+        Cipher {
+            secret_key: secret_key.as_bytes().to_vec(),
+            keys: Default::default(),
+        }
+    }
+
+    fn key(&self, salt: &[u8]) -> Aes256Gcm {
+        let mut keys = self.keys.lock().unwrap();
+
+        keys.entry(salt.to_vec())
+            .or_insert_with(|| {
+                let mut key = [0u8; 32];
+                pbkdf2::pbkdf2::<Hmac<Sha256>>(&self.secret_key, salt, PBKDF2_ROUNDS, &mut key);
+
+                Aes256Gcm::new(GenericArray::from_slice(&key))
+            })
+            .clone()
+    }
+
+    pub fn encrypt(&self, credentials: &Credentials) -> Result<EncryptedCredentials, &'static str> {
+        let plaintext =
+            serde_json::to_vec(credentials).map_err(|_| "Could not encode credentials.")?;
+
+        let mut salt = [0u8; SALT_LEN];
+        let mut nonce = [0u8; NONCE_LEN];
+        rand::thread_rng().fill(&mut salt);
+        rand::thread_rng().fill(&mut nonce);
+
+        let ciphertext = self
+            .key(&salt)
+            .encrypt(GenericArray::from_slice(&nonce), plaintext.as_slice())
+            .map_err(|_| "Could not encrypt credentials.")?;
+
+        let mut bytes = vec![VERSION];
+        bytes.extend(&salt);
+        bytes.extend(&nonce);
+        bytes.extend(ciphertext);
+
+        Ok(EncryptedCredentials(bytes))
+    }
+
+    pub fn decrypt(&self, encrypted: &EncryptedCredentials) -> Result<Credentials, &'static str> {
+        let bytes = &encrypted.0;
+
+        if bytes.len() <= 1 + SALT_LEN + NONCE_LEN || bytes[0] != VERSION {
+            return Err("Invalid encrypted credentials.");
+        }
+
+        let (salt, rest) = bytes[1..].split_at(SALT_LEN);
+        let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+
+        let plaintext = self
+            .key(salt)
+            .decrypt(GenericArray::from_slice(nonce), ciphertext)
+            .map_err(|_| "Could not decrypt credentials: was the secret key changed?")?;
+
+        serde_json::from_slice(&plaintext).map_err(|_| "Could not decode credentials.")
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn credentials() -> Credentials {
+        Credentials {
+            auth: Some(Auth::Bearer("token".to_owned())),
+            headers: vec![("X-Api-Key".to_owned(), "key".to_owned())],
+        }
+    }
+
+    fn encoded(credentials: &Credentials) -> String {
+        serde_json::to_string(credentials).unwrap()
+    }
+
+    #[test]
+    fn round_trip() {
+        let cipher = Cipher::new("secret");
+        let encrypted = cipher.encrypt(&credentials()).unwrap();
+
+        assert!(!encrypted.0.windows(5).any(|part| part == b"token"));
+
+        let decrypted = Cipher::new("secret").decrypt(&encrypted).unwrap();
+        assert_eq!(encoded(&decrypted), encoded(&credentials()));
+    }
+
+    #[test]
+    fn salts_differ() {
+        let cipher = Cipher::new("secret");
+        let first = cipher.encrypt(&credentials()).unwrap();
+        let second = cipher.encrypt(&credentials()).unwrap();
+
+        assert_ne!(first.0[1..1 + SALT_LEN], second.0[1..1 + SALT_LEN]);
+    }
+
+    #[test]
+    fn tampered_is_rejected() {
+        let cipher = Cipher::new("secret");
+        let encrypted = cipher.encrypt(&credentials()).unwrap();
+
+        for i in 0..encrypted.0.len() {
+            let mut tampered = encrypted.clone();
+            tampered.0[i] ^= 1;
+            assert!(cipher.decrypt(&tampered).is_err(), "byte {}", i);
+        }
+
+        let mut truncated = encrypted;
+        truncated.0.pop();
+        assert!(cipher.decrypt(&truncated).is_err());
+    }
+
+    #[test]
+    fn wrong_key_is_rejected() {
+        let encrypted = Cipher::new("secret").encrypt(&credentials()).unwrap();
+
+        assert!(Cipher::new("other").decrypt(&encrypted).is_err());
+    }
+}
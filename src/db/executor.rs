@@ -1,9 +1,14 @@
 use actix::prelude::*;
 use diesel::prelude::*;
 use diesel::query_dsl::LoadQuery;
+use std::collections::HashMap;
 use std::rc::Rc;
 
 use crate::db::{self, models::*, schema};
+use crate::retention::RetentionPolicy;
+
+/// Maximum number of values in a single `IN (...)` clause.
+pub(super) const MAX_IN_VALUES: usize = 500;
 
 pub struct Executor {
     conn: Rc<SqliteConnection>,
@@ -96,7 +101,8 @@ impl Handler<RemoveSubscription> for Executor {
     type Result = <RemoveSubscription as Message>::Result;
 
     fn handle(&mut self, msg: RemoveSubscription, ctx: &mut Self::Context) -> Self::Result {
-        use schema::items::dsl::*;
+        use schema::items::dsl::{id as item_id, items, subscription_id};
+        use schema::rules::dsl::{rules, subscription_id as rule_subscription_id};
         use schema::subscriptions::dsl::*;
 
         self.conn.clone().transaction(|| {
@@ -114,9 +120,27 @@ impl Handler<RemoveSubscription> for Executor {
                 }
 
                 // Remove subscription's items
-                diesel::delete(items.filter(subscription_id.eq(subscription.id)))
+                let item_ids: Vec<db::Id> = items
+                    .filter(subscription_id.eq(subscription.id))
+                    .select(item_id)
+                    .load(self.conn.as_ref())?;
+                self.handle(DeleteItems(item_ids), ctx)?;
+
+                // Remove subscription's rules
+                diesel::delete(rules.filter(rule_subscription_id.eq(subscription.id)))
                     .execute(self.conn.as_ref())?;
 
+                // Remove subscription's favicon
+                diesel::delete(schema::favicons::table.find(subscription.id))
+                    .execute(self.conn.as_ref())?;
+
+                // Remove subscription's purged items
+                diesel::delete(
+                    schema::purged_items::table
+                        .filter(schema::purged_items::subscription_id.eq(subscription.id)),
+                )
+                .execute(self.conn.as_ref())?;
+
                 // Remove subscription
                 diesel::delete(subscriptions.find(subscription.id)).execute(self.conn.as_ref())?;
             }
@@ -187,6 +211,34 @@ impl Handler<TransformSubscription> for Executor {
 }
 
 
+pub struct TransformCategory(pub String, pub Box<dyn FnOnce(&mut Category) + Send>);
+
+impl Message for TransformCategory {
+    type Result = QueryResult<Category>;
+}
+
+impl Handler<TransformCategory> for Executor {
+    type Result = <TransformCategory as Message>::Result;
+
+    fn handle(&mut self, msg: TransformCategory, ctx: &mut Self::Context) -> Self::Result {
+        let (name, transform) = (msg.0, msg.1);
+
+        self.conn.clone().transaction(|| {
+            let mut category = self
+                .handle(GetCategoryByName(name), ctx)?
+                .ok_or(diesel::result::Error::NotFound)?;
+
+            transform(&mut category);
+
+            diesel::update(&category)
+                .set(&category)
+                .execute(self.conn.as_ref())
+                .map(|_| category)
+        })
+    }
+}
+
+
 pub struct CreateCategory {
     pub name: String,
 }
@@ -385,61 +437,816 @@ impl Handler<GetSubscriptionCategories> for Executor {
 }
 
 
-pub struct CreateItem(pub NewItem);
+pub struct StoreItems {
+    pub new_items: Vec<NewItem>,
+    /// Labels, tags and enclosures of new items, by GUID.
+    pub extras: HashMap<String, NewItemExtras>,
+    /// Updated items, with their current tags and enclosures.
+    ///
+    /// Labels are left alone, and enclosures which are gone are kept if
+    /// they were downloaded.
+    pub updates: Vec<(ItemUpdate, NewItemExtras)>,
+    pub mark_unread: Vec<db::Id>,
+}
 
-impl Message for CreateItem {
-    type Result = QueryResult<Item>;
+impl Message for StoreItems {
+    type Result = QueryResult<()>;
 }
 
-impl Handler<CreateItem> for Executor {
-    type Result = <CreateItem as Message>::Result;
+impl Handler<StoreItems> for Executor {
+    type Result = <StoreItems as Message>::Result;
 
-    fn handle(&mut self, msg: CreateItem, _: &mut Self::Context) -> Self::Result {
+    fn handle(&mut self, msg: StoreItems, _: &mut Self::Context) -> Self::Result {
         self.conn.transaction(|| {
             use schema::items::dsl::*;
 
             diesel::insert_into(items)
+                .values(&msg.new_items)
+                .execute(self.conn.as_ref())?;
+
+            for new_item in &msg.new_items {
+                let extras = match msg.extras.get(&new_item.guid) {
+                    Some(extras) => extras,
+                    None => continue,
+                };
+
+                let item_id = items
+                    .filter(subscription_id.eq(new_item.subscription_id))
+                    .filter(guid.eq(&new_item.guid))
+                    .select(id)
+                    .first(self.conn.as_ref())?;
+
+                let labels: Vec<_> = extras
+                    .labels
+                    .iter()
+                    .map(|name| NewItemLabel {
+                        item_id,
+                        name: name.clone(),
+                    })
+                    .collect();
+
+                diesel::insert_or_ignore_into(schema::item_labels::table)
+                    .values(&labels)
+                    .execute(self.conn.as_ref())?;
+
+                let tags: Vec<_> = extras
+                    .tags
+                    .iter()
+                    .map(|name| NewItemTag {
+                        item_id,
+                        name: name.clone(),
+                    })
+                    .collect();
+
+                diesel::insert_or_ignore_into(schema::item_tags::table)
+                    .values(&tags)
+                    .execute(self.conn.as_ref())?;
+
+                for enclosure in &extras.enclosures {
+                    diesel::insert_into(schema::enclosures::table)
+                        .values((schema::enclosures::item_id.eq(item_id), enclosure))
+                        .execute(self.conn.as_ref())?;
+                }
+            }
+
+            for (update, extras) in &msg.updates {
+                diesel::update(update)
+                    .set(update)
+                    .execute(self.conn.as_ref())?;
+
+                self.update_item_tags(update.id, &extras.tags)?;
+                self.update_item_enclosures(update.id, &extras.enclosures)?;
+            }
+
+            if !msg.mark_unread.is_empty() {
+                diesel::update(items.filter(id.eq_any(msg.mark_unread)))
+                    .set((is_read.eq(false), read_at.eq(None::<chrono::NaiveDateTime>)))
+                    .execute(self.conn.as_ref())?;
+            }
+
+            Ok(())
+        })
+    }
+}
+
+
+impl Executor {
+    /// Replace the tags of an item.
+    fn update_item_tags(&self, id: db::Id, tags: &[String]) -> QueryResult<()> {
+        use schema::item_tags::dsl::*;
+
+        diesel::delete(item_tags.filter(item_id.eq(id)).filter(name.ne_all(tags)))
+            .execute(self.conn.as_ref())?;
+
+        let new_tags: Vec<_> = tags
+            .iter()
+            .map(|tag| NewItemTag {
+                item_id: id,
+                name: tag.clone(),
+            })
+            .collect();
+
+        diesel::insert_or_ignore_into(item_tags)
+            .values(&new_tags)
+            .execute(self.conn.as_ref())?;
+
+        Ok(())
+    }
+
+    /// Insert or update the enclosures of an item, by URL.
+    ///
+    /// Enclosures no longer in `new_enclosures` are removed, unless they
+    /// were downloaded.
+    fn update_item_enclosures(
+        &self,
+        id: db::Id,
+        new_enclosures: &[NewEnclosure],
+    ) -> QueryResult<()> {
+        use schema::enclosures::dsl::*;
+
+        let urls: Vec<_> = new_enclosures.iter().map(|e| e.url.as_str()).collect();
+        diesel::delete(
+            enclosures
+                .filter(item_id.eq(id))
+                .filter(url.ne_all(urls))
+                .filter(
+                    download_status
+                        .is_null()
+                        .or(download_status.eq(Enclosure::FAILED)),
+                ),
+        )
+        .execute(self.conn.as_ref())?;
+
+        for enclosure in new_enclosures {
+            let updated = diesel::update(
+                enclosures
+                    .filter(item_id.eq(id))
+                    .filter(url.eq(&enclosure.url)),
+            )
+            .set(enclosure)
+            .execute(self.conn.as_ref())?;
+
+            if updated == 0 {
+                diesel::insert_into(enclosures)
+                    .values((item_id.eq(id), enclosure))
+                    .execute(self.conn.as_ref())?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+
+pub struct DeleteItems(pub Vec<db::Id>);
+
+impl Message for DeleteItems {
+    type Result = QueryResult<usize>;
+}
+
+impl Handler<DeleteItems> for Executor {
+    type Result = <DeleteItems as Message>::Result;
+
+    fn handle(&mut self, msg: DeleteItems, _: &mut Self::Context) -> Self::Result {
+        use schema::enclosures::dsl::{enclosures, item_id as enclosure_item_id};
+        use schema::item_labels::dsl::{item_id, item_labels};
+        use schema::item_tags::dsl::{item_id as tag_item_id, item_tags};
+        use schema::items::dsl::*;
+        use schema::operation_items::dsl::{item_id as operation_item_id, operation_items};
+
+        self.conn.transaction(|| {
+            let mut count = 0;
+            for ids in msg.0.chunks(MAX_IN_VALUES) {
+                diesel::delete(operation_items.filter(operation_item_id.eq_any(ids)))
+                    .execute(self.conn.as_ref())?;
+                diesel::delete(item_labels.filter(item_id.eq_any(ids)))
+                    .execute(self.conn.as_ref())?;
+                diesel::delete(item_tags.filter(tag_item_id.eq_any(ids)))
+                    .execute(self.conn.as_ref())?;
+                diesel::delete(enclosures.filter(enclosure_item_id.eq_any(ids)))
+                    .execute(self.conn.as_ref())?;
+
+                count +=
+                    diesel::delete(items.filter(id.eq_any(ids))).execute(self.conn.as_ref())?;
+            }
+
+            Ok(count)
+        })
+    }
+}
+
+
+/// Delete items according to retention policies.
+///
+/// The entries of deleted items are recorded, so they aren't stored again
+/// on the next refresh.
+///
+/// Result is the IDs of the items deleted, or that would be if `dry_run` is
+/// set.
+pub struct PurgeItems {
+    pub default_policy: RetentionPolicy,
+    pub dry_run: bool,
+}
+
+impl Message for PurgeItems {
+    type Result = QueryResult<Vec<db::Id>>;
+}
+
+impl Handler<PurgeItems> for Executor {
+    type Result = <PurgeItems as Message>::Result;
+
+    fn handle(&mut self, msg: PurgeItems, ctx: &mut Self::Context) -> Self::Result {
+        use schema::items::dsl::*;
+
+        self.conn.clone().transaction(|| {
+            let subscriptions: Vec<Subscription> =
+                schema::subscriptions::table.load(self.conn.as_ref())?;
+
+            let mut to_delete = std::collections::HashSet::new();
+
+            for subscription in subscriptions {
+                let categories = self.handle(GetSubscriptionCategories(subscription.id), ctx)?;
+                let policy = msg
+                    .default_policy
+                    .for_subscription(&subscription, &categories);
+
+                let purgeable = || {
+                    items
+                        .filter(subscription_id.eq(subscription.id))
+                        .filter(is_starred.eq(false))
+                        .filter(is_kept_unread.eq(false))
+                        .select(id)
+                };
+
+                if let Some(days) = policy.read_days {
+                    let limit =
+                        chrono::Utc::now().naive_utc() - chrono::Duration::days(days.into());
+
+                    // Items read before read dates were recorded use their publication date
+                    let ids: Vec<db::Id> = purgeable()
+                        .filter(is_read.eq(true))
+                        .filter(
+                            read_at
+                                .lt(limit)
+                                .or(read_at.is_null().and(published.lt(limit))),
+                        )
+                        .load(self.conn.as_ref())?;
+                    to_delete.extend(ids);
+                }
+
+                if let Some(max) = policy.max_items {
+                    let ids: Vec<db::Id> = purgeable()
+                        .order(published.desc())
+                        .limit(i64::MAX)
+                        .offset(max.into())
+                        .load(self.conn.as_ref())?;
+                    to_delete.extend(ids);
+                }
+            }
+
+            let to_delete: Vec<db::Id> = to_delete.into_iter().collect();
+            if msg.dry_run {
+                return Ok(to_delete);
+            }
+
+            for ids in to_delete.chunks(MAX_IN_VALUES) {
+                let purged: Vec<PurgedItem> = items
+                    .filter(id.eq_any(ids))
+                    .select((subscription_id, guid))
+                    .load(self.conn.as_ref())?;
+
+                for chunk in purged.chunks(MAX_IN_VALUES / 2) {
+                    diesel::insert_or_ignore_into(schema::purged_items::table)
+                        .values(chunk)
+                        .execute(self.conn.as_ref())?;
+                }
+            }
+
+            self.handle(DeleteItems(to_delete.clone()), ctx)?;
+
+            Ok(to_delete)
+        })
+    }
+}
+
+
+/// Forget the purged items of a subscription whose entry isn't in its feed
+/// anymore, so they can't come back.
+pub struct ForgetPurgedItems {
+    pub subscription_id: db::Id,
+    /// GUIDs of the entries in the feed.
+    pub guids: Vec<String>,
+}
+
+impl Message for ForgetPurgedItems {
+    type Result = QueryResult<()>;
+}
+
+impl Handler<ForgetPurgedItems> for Executor {
+    type Result = <ForgetPurgedItems as Message>::Result;
+
+    fn handle(&mut self, msg: ForgetPurgedItems, _: &mut Self::Context) -> Self::Result {
+        use schema::purged_items::dsl::*;
+
+        self.conn.transaction(|| {
+            let purged: Vec<String> = purged_items
+                .filter(subscription_id.eq(msg.subscription_id))
+                .select(guid)
+                .load(self.conn.as_ref())?;
+
+            let in_feed: std::collections::HashSet<&String> = msg.guids.iter().collect();
+            let gone: Vec<String> = purged
+                .into_iter()
+                .filter(|purged_guid| !in_feed.contains(purged_guid))
+                .collect();
+
+            for guids in gone.chunks(MAX_IN_VALUES) {
+                diesel::delete(
+                    purged_items
+                        .filter(subscription_id.eq(msg.subscription_id))
+                        .filter(guid.eq_any(guids)),
+                )
+                .execute(self.conn.as_ref())?;
+            }
+
+            Ok(())
+        })
+    }
+}
+
+
+/// Change the state of items, unset fields are left unchanged.
+///
+/// Items kept unread are marked unread, and items marked read aren't kept
+/// unread anymore.
+///
+/// If `operation` is set, the changes are recorded as an operation of that
+/// kind, see `RevertOperation`.
+pub struct UpdateItemsState {
+    pub ids: Vec<db::Id>,
+    pub read: Option<bool>,
+    pub starred: Option<bool>,
+    pub kept_unread: Option<bool>,
+    pub operation: Option<String>,
+}
+
+impl Message for UpdateItemsState {
+    type Result = QueryResult<()>;
+}
+
+impl Handler<UpdateItemsState> for Executor {
+    type Result = <UpdateItemsState as Message>::Result;
+
+    fn handle(&mut self, msg: UpdateItemsState, _: &mut Self::Context) -> Self::Result {
+        use schema::items::dsl::*;
+
+        let now = chrono::Utc::now().naive_utc();
+        let read = match msg.kept_unread {
+            Some(true) => Some(false),
+            _ => msg.read,
+        };
+        let kept_unread = match read {
+            Some(true) => Some(false),
+            _ => msg.kept_unread,
+        };
+
+        self.conn.transaction(|| {
+            if let Some(kind) = msg.operation {
+                let mut changed = Vec::new();
+                for ids in msg.ids.chunks(MAX_IN_VALUES) {
+                    let states: Vec<(db::Id, bool, bool, bool, _, _)> = items
+                        .filter(id.eq_any(ids))
+                        .select((id, is_read, is_starred, is_kept_unread, read_at, starred_at))
+                        .load(self.conn.as_ref())?;
+
+                    changed.extend(states.into_iter().filter(|state| {
+                        read.map_or(false, |val| val != state.1)
+                            || msg.starred.map_or(false, |val| val != state.2)
+                            || kept_unread.map_or(false, |val| val != state.3)
+                    }));
+                }
+
+                if !changed.is_empty() {
+                    use schema::operation_items::dsl::operation_items;
+                    use schema::operations::dsl::{id as operation_id, operations};
+
+                    diesel::insert_into(operations)
+                        .values(&NewOperation {
+                            kind,
+                            created_at: now,
+                            is_read: read,
+                            is_starred: msg.starred,
+                            is_kept_unread: kept_unread,
+                            item_count: changed.len() as i32,
+                        })
+                        .execute(self.conn.as_ref())?;
+                    let operation: Operation = operations
+                        .order(operation_id.desc())
+                        .first(self.conn.as_ref())?;
+
+                    let previous_states: Vec<_> = changed
+                        .into_iter()
+                        .map(|state| OperationItem {
+                            operation_id: operation.id,
+                            item_id: state.0,
+                            was_read: state.1,
+                            was_starred: state.2,
+                            was_kept_unread: state.3,
+                            read_at: state.4,
+                            starred_at: state.5,
+                        })
+                        .collect();
+
+                    // SQLite limits the number of values in a query
+                    for chunk in previous_states.chunks(MAX_IN_VALUES / 7) {
+                        diesel::insert_into(operation_items)
+                            .values(chunk)
+                            .execute(self.conn.as_ref())?;
+                    }
+                }
+            }
+
+            for ids in msg.ids.chunks(MAX_IN_VALUES) {
+                match read {
+                    Some(true) => {
+                        // Keep the date of items already read
+                        diesel::update(items.filter(id.eq_any(ids)).filter(is_read.eq(false)))
+                            .set((is_read.eq(true), read_at.eq(now)))
+                            .execute(self.conn.as_ref())?;
+                    }
+                    Some(false) => {
+                        diesel::update(items.filter(id.eq_any(ids)))
+                            .set((is_read.eq(false), read_at.eq(None::<chrono::NaiveDateTime>)))
+                            .execute(self.conn.as_ref())?;
+                    }
+                    None => (),
+                }
+
+                match msg.starred {
+                    Some(true) => {
+                        diesel::update(items.filter(id.eq_any(ids)).filter(is_starred.eq(false)))
+                            .set((is_starred.eq(true), starred_at.eq(now)))
+                            .execute(self.conn.as_ref())?;
+                    }
+                    Some(false) => {
+                        diesel::update(items.filter(id.eq_any(ids)))
+                            .set((
+                                is_starred.eq(false),
+                                starred_at.eq(None::<chrono::NaiveDateTime>),
+                            ))
+                            .execute(self.conn.as_ref())?;
+                    }
+                    None => (),
+                }
+
+                if let Some(val) = kept_unread {
+                    diesel::update(items.filter(id.eq_any(ids)))
+                        .set(is_kept_unread.eq(val))
+                        .execute(self.conn.as_ref())?;
+                }
+            }
+
+            Ok(())
+        })
+    }
+}
+
+
+/// Restore the state items had before an operation.
+///
+/// Only the states changed by the operation are restored, on items still in
+/// the state it gave them, and only if it was created after `min_date` and
+/// wasn't already reverted. Result is the IDs of the items restored, if the
+/// operation was reverted.
+pub struct RevertOperation {
+    pub id: db::Id,
+    pub min_date: chrono::NaiveDateTime,
+}
+
+impl Message for RevertOperation {
+    type Result = QueryResult<Option<Vec<db::Id>>>;
+}
+
+impl Handler<RevertOperation> for Executor {
+    type Result = <RevertOperation as Message>::Result;
+
+    fn handle(&mut self, msg: RevertOperation, _: &mut Self::Context) -> Self::Result {
+        use schema::items::dsl::*;
+        use schema::operation_items::dsl as oi;
+        use schema::operations::dsl as o;
+
+        self.conn.transaction(|| {
+            let operation: Option<Operation> = o::operations
+                .find(msg.id)
+                .filter(o::is_reverted.eq(false))
+                .filter(o::created_at.ge(msg.min_date))
+                .first(self.conn.as_ref())
+                .optional()?;
+            let operation = match operation {
+                Some(operation) => operation,
+                None => return Ok(None),
+            };
+
+            let previous_states: Vec<OperationItem> = oi::operation_items
+                .filter(oi::operation_id.eq(operation.id))
+                .load(self.conn.as_ref())?;
+
+            // States changed since the operation are left alone
+            let mut restored_ids = Vec::new();
+            for state in &previous_states {
+                let mut restored = 0;
+
+                if let Some(target) = operation.is_read {
+                    restored +=
+                        diesel::update(items.find(state.item_id).filter(is_read.eq(target)))
+                            .set((is_read.eq(state.was_read), read_at.eq(state.read_at)))
+                            .execute(self.conn.as_ref())?;
+                }
+
+                if let Some(target) = operation.is_kept_unread {
+                    restored +=
+                        diesel::update(items.find(state.item_id).filter(is_kept_unread.eq(target)))
+                            .set(is_kept_unread.eq(state.was_kept_unread))
+                            .execute(self.conn.as_ref())?;
+                }
+
+                if let Some(target) = operation.is_starred {
+                    restored +=
+                        diesel::update(items.find(state.item_id).filter(is_starred.eq(target)))
+                            .set((
+                                is_starred.eq(state.was_starred),
+                                starred_at.eq(state.starred_at),
+                            ))
+                            .execute(self.conn.as_ref())?;
+                }
+
+                if restored > 0 {
+                    restored_ids.push(state.item_id);
+                }
+            }
+
+            diesel::update(&operation)
+                .set(o::is_reverted.eq(true))
+                .execute(self.conn.as_ref())?;
+
+            Ok(Some(restored_ids))
+        })
+    }
+}
+
+
+/// Delete operations created before a date, they can't be reverted anymore.
+pub struct PurgeOperations(pub chrono::NaiveDateTime);
+
+impl Message for PurgeOperations {
+    type Result = QueryResult<usize>;
+}
+
+impl Handler<PurgeOperations> for Executor {
+    type Result = <PurgeOperations as Message>::Result;
+
+    fn handle(&mut self, msg: PurgeOperations, _: &mut Self::Context) -> Self::Result {
+        use schema::operation_items::dsl as oi;
+        use schema::operations::dsl as o;
+
+        self.conn.transaction(|| {
+            let old_ids = o::operations.filter(o::created_at.lt(msg.0)).select(o::id);
+
+            diesel::delete(oi::operation_items.filter(oi::operation_id.eq_any(old_ids)))
+                .execute(self.conn.as_ref())?;
+
+            diesel::delete(o::operations.filter(o::created_at.lt(msg.0)))
+                .execute(self.conn.as_ref())
+        })
+    }
+}
+
+
+pub struct CreateSavedSearch(pub NewSavedSearch);
+
+impl Message for CreateSavedSearch {
+    type Result = QueryResult<SavedSearch>;
+}
+
+impl Handler<CreateSavedSearch> for Executor {
+    type Result = <CreateSavedSearch as Message>::Result;
+
+    fn handle(&mut self, msg: CreateSavedSearch, _: &mut Self::Context) -> Self::Result {
+        self.conn.transaction(|| {
+            use schema::saved_searches::dsl::*;
+
+            diesel::insert_into(saved_searches)
+                .values(&msg.0)
+                .execute(self.conn.as_ref())?;
+
+            saved_searches.order(id.desc()).first(self.conn.as_ref())
+        })
+    }
+}
+
+
+pub struct RemoveSavedSearch(pub String);
+
+impl Message for RemoveSavedSearch {
+    type Result = QueryResult<()>;
+}
+
+impl Handler<RemoveSavedSearch> for Executor {
+    type Result = <RemoveSavedSearch as Message>::Result;
+
+    fn handle(&mut self, msg: RemoveSavedSearch, _: &mut Self::Context) -> Self::Result {
+        use schema::saved_searches::dsl::*;
+
+        diesel::delete(saved_searches.filter(name.eq(msg.0)))
+            .execute(self.conn.as_ref())
+            .map(|_| ())
+    }
+}
+
+
+pub struct CreateRule(pub NewRule);
+
+impl Message for CreateRule {
+    type Result = QueryResult<Rule>;
+}
+
+impl Handler<CreateRule> for Executor {
+    type Result = <CreateRule as Message>::Result;
+
+    fn handle(&mut self, msg: CreateRule, _: &mut Self::Context) -> Self::Result {
+        self.conn.transaction(|| {
+            use schema::rules::dsl::*;
+
+            diesel::insert_into(rules)
                 .values(&msg.0)
                 .execute(self.conn.as_ref())?;
 
-            items.order(id.desc()).first(self.conn.as_ref())
+            rules.order(id.desc()).first(self.conn.as_ref())
         })
     }
 }
 
 
-pub struct GetItem(pub db::Id);
+/// Remove a rule by name.
+///
+/// Result is whether a rule was found.
+pub struct RemoveRule(pub String);
+
+impl Message for RemoveRule {
+    type Result = QueryResult<bool>;
+}
+
+impl Handler<RemoveRule> for Executor {
+    type Result = <RemoveRule as Message>::Result;
+
+    fn handle(&mut self, msg: RemoveRule, _: &mut Self::Context) -> Self::Result {
+        use schema::rules::dsl::*;
 
-impl Message for GetItem {
-    type Result = QueryResult<Item>;
+        diesel::delete(rules.filter(name.eq(msg.0)))
+            .execute(self.conn.as_ref())
+            .map(|count| count > 0)
+    }
 }
 
-impl Handler<GetItem> for Executor {
-    type Result = <GetItem as Message>::Result;
 
-    fn handle(&mut self, msg: GetItem, _: &mut Self::Context) -> Self::Result {
+/// Add labels to items, ignoring the ones they already have.
+pub struct AddItemLabels(pub Vec<NewItemLabel>);
+
+impl Message for AddItemLabels {
+    type Result = QueryResult<()>;
+}
+
+impl Handler<AddItemLabels> for Executor {
+    type Result = <AddItemLabels as Message>::Result;
+
+    fn handle(&mut self, msg: AddItemLabels, _: &mut Self::Context) -> Self::Result {
+        use schema::item_labels::dsl::*;
+
+        self.conn.transaction(|| {
+            for labels in msg.0.chunks(MAX_IN_VALUES) {
+                diesel::insert_or_ignore_into(item_labels)
+                    .values(labels)
+                    .execute(self.conn.as_ref())?;
+            }
+
+            Ok(())
+        })
+    }
+}
+
+
+/// Remove labels from items, ignoring the ones they don't have.
+pub struct RemoveItemLabels(pub Vec<NewItemLabel>);
+
+impl Message for RemoveItemLabels {
+    type Result = QueryResult<()>;
+}
+
+impl Handler<RemoveItemLabels> for Executor {
+    type Result = <RemoveItemLabels as Message>::Result;
+
+    fn handle(&mut self, msg: RemoveItemLabels, _: &mut Self::Context) -> Self::Result {
+        use schema::item_labels::dsl::*;
+
+        self.conn.transaction(|| {
+            for label in msg.0 {
+                diesel::delete(
+                    item_labels
+                        .filter(item_id.eq(label.item_id))
+                        .filter(name.eq(label.name)),
+                )
+                .execute(self.conn.as_ref())?;
+            }
+
+            Ok(())
+        })
+    }
+}
+
+
+/// Replace the content of items, keeping their raw content.
+pub struct UpdateItemsContent(pub Vec<(db::Id, String)>);
+
+impl Message for UpdateItemsContent {
+    type Result = QueryResult<()>;
+}
+
+impl Handler<UpdateItemsContent> for Executor {
+    type Result = <UpdateItemsContent as Message>::Result;
+
+    fn handle(&mut self, msg: UpdateItemsContent, _: &mut Self::Context) -> Self::Result {
+        use schema::items::dsl::*;
+
+        self.conn.transaction(|| {
+            for (item_id, new_content) in msg.0 {
+                diesel::update(items.find(item_id))
+                    .set(content.eq(new_content))
+                    .execute(self.conn.as_ref())?;
+            }
+
+            Ok(())
+        })
+    }
+}
+
+
+pub struct SetItemFullContent {
+    pub id: db::Id,
+    pub full_content: String,
+}
+
+impl Message for SetItemFullContent {
+    type Result = QueryResult<()>;
+}
+
+impl Handler<SetItemFullContent> for Executor {
+    type Result = <SetItemFullContent as Message>::Result;
+
+    fn handle(&mut self, msg: SetItemFullContent, _: &mut Self::Context) -> Self::Result {
         use schema::items::dsl::*;
 
-        items.find(msg.0).get_result(self.conn.as_ref())
+        diesel::update(items.find(msg.id))
+            .set(full_content.eq(msg.full_content))
+            .execute(self.conn.as_ref())
+            .map(|_| ())
     }
 }
 
 
-pub struct UpdateItem(pub Item);
+pub struct UpdateEnclosure(pub Enclosure);
+
+impl Message for UpdateEnclosure {
+    type Result = QueryResult<Enclosure>;
+}
+
+impl Handler<UpdateEnclosure> for Executor {
+    type Result = <UpdateEnclosure as Message>::Result;
+
+    fn handle(&mut self, msg: UpdateEnclosure, _: &mut Self::Context) -> Self::Result {
+        let enclosure = msg.0;
 
-impl Message for UpdateItem {
-    type Result = QueryResult<Item>;
+        diesel::update(&enclosure)
+            .set(&enclosure)
+            .execute(self.conn.as_ref())
+            .map(|_| enclosure)
+    }
 }
 
-impl Handler<UpdateItem> for Executor {
-    type Result = <UpdateItem as Message>::Result;
 
-    fn handle(&mut self, msg: UpdateItem, _: &mut Self::Context) -> Self::Result {
-        let item = msg.0;
+/// Store a subscription's favicon, replacing the previous one.
+pub struct SetFavicon(pub Favicon);
+
+impl Message for SetFavicon {
+    type Result = QueryResult<()>;
+}
+
+impl Handler<SetFavicon> for Executor {
+    type Result = <SetFavicon as Message>::Result;
 
-        diesel::update(&item)
-            .set(&item)
+    fn handle(&mut self, msg: SetFavicon, _: &mut Self::Context) -> Self::Result {
+        diesel::replace_into(schema::favicons::table)
+            .values(&msg.0)
             .execute(self.conn.as_ref())
-            .map(|_| item)
+            .map(|_| ())
     }
 }
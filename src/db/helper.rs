@@ -1,12 +1,15 @@
 use actix::prelude::*;
 use actix_web::ResponseError;
 use diesel::prelude::*;
+use diesel::sqlite::Sqlite;
 use futures::future::{self, TryFutureExt};
+use std::collections::HashMap;
 use std::fmt::{self, Display};
 use std::future::Future;
 
 use super::{executor::*, models::*, schema, Id};
 use crate::config::Config;
+use crate::retention::RetentionPolicy;
 
 #[derive(Debug)]
 pub enum Error {
@@ -16,6 +19,18 @@ pub enum Error {
 
 impl ResponseError for Error {}
 
+impl Error {
+    /// Whether the error is due to a row conflicting with an existing one.
+    pub fn is_unique_violation(&self) -> bool {
+        use diesel::result::{DatabaseErrorKind, Error::DatabaseError};
+
+        matches!(
+            self,
+            Self::DatabaseError(DatabaseError(DatabaseErrorKind::UniqueViolation, _))
+        )
+    }
+}
+
 impl Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
@@ -26,6 +41,137 @@ impl Display for Error {
 }
 
 
+/// Criteria for finding items, unset fields match everything.
+#[derive(Debug, Clone, Default)]
+pub struct ItemFilter {
+    pub read: Option<bool>,
+    pub starred: Option<bool>,
+    pub kept_unread: Option<bool>,
+    pub subscription: Option<Id>,
+    /// Name of a category the item's subscription is in, or of a label the item has.
+    pub category: Option<String>,
+    /// Tag given to the item by its feed, case-insensitive.
+    pub tag: Option<String>,
+    /// Full-text search query, in SQLite FTS5 syntax.
+    pub search: Option<String>,
+    pub min_date: Option<chrono::NaiveDateTime>,
+    pub max_date: Option<chrono::NaiveDateTime>,
+    /// Date found items are sorted by, newest first.
+    pub order: ItemOrder,
+}
+
+/// Dates items can be sorted by.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ItemOrder {
+    Published,
+    /// Items never marked read, or marked read before this date was
+    /// recorded, come last.
+    Read,
+    /// Same as `Read`, for stars.
+    Starred,
+}
+
+impl Default for ItemOrder {
+    fn default() -> Self {
+        ItemOrder::Published
+    }
+}
+
+impl ItemFilter {
+    /// Restrict the filter to items also matching `search`.
+    pub fn add_search(&mut self, search: String) {
+        self.search = Some(match self.search.take() {
+            Some(existing) => format!("({}) AND ({})", existing, search),
+            None => search,
+        });
+    }
+
+    fn into_query(self) -> schema::items::BoxedQuery<'static, Sqlite> {
+        use diesel::dsl::sql;
+        use diesel::sql_types::{Bool, Text};
+        use schema::items::dsl::*;
+
+        let mut query = items.into_boxed();
+
+        if let Some(val) = self.read {
+            query = query.filter(is_read.eq(val));
+        }
+
+        if let Some(val) = self.starred {
+            query = query.filter(is_starred.eq(val));
+        }
+
+        if let Some(val) = self.kept_unread {
+            query = query.filter(is_kept_unread.eq(val));
+        }
+
+        if let Some(val) = self.subscription {
+            query = query.filter(subscription_id.eq(val));
+        }
+
+        if let Some(val) = self.category {
+            use schema::categories::dsl::{categories, name};
+            use schema::subscription_categories::dsl as sc;
+
+            use schema::item_labels::dsl as il;
+
+            let subscription_ids = sc::subscription_categories
+                .inner_join(categories)
+                .filter(name.eq(val.clone()))
+                .select(sc::subscription_id);
+            let labeled_ids = il::item_labels.filter(il::name.eq(val)).select(il::item_id);
+
+            query = query.filter(
+                subscription_id
+                    .eq_any(subscription_ids)
+                    .or(id.eq_any(labeled_ids)),
+            );
+        }
+
+        if let Some(val) = self.tag {
+            use schema::item_tags::dsl as it;
+
+            let tagged_ids = it::item_tags.filter(it::name.eq(val)).select(it::item_id);
+
+            query = query.filter(id.eq_any(tagged_ids));
+        }
+
+        if let Some(val) = self.search {
+            use schema::items_fts::dsl::{items_fts, rowid};
+
+            let matching_ids = items_fts
+                .select(rowid)
+                .filter(sql::<Bool>("items_fts MATCH ").bind::<Text, _>(val));
+
+            query = query.filter(id.eq_any(matching_ids));
+        }
+
+        if let Some(val) = self.min_date {
+            query = query.filter(published.ge(val));
+        }
+
+        if let Some(val) = self.max_date {
+            query = query.filter(published.le(val));
+        }
+
+        query
+    }
+
+    fn into_sorted_query(self) -> schema::items::BoxedQuery<'static, Sqlite> {
+        use schema::items::dsl::*;
+
+        let order = self.order;
+        let query = self.into_query();
+
+        match order {
+            ItemOrder::Published => query.order(published.desc()),
+            ItemOrder::Read => query.order((read_at.desc(), published.desc())),
+            ItemOrder::Starred => query.order((starred_at.desc(), published.desc())),
+        }
+    }
+}
+
+
 pub trait DatabaseFuture<I>: Future<Output = Result<I, Error>> {}
 impl<I, T: Future<Output = Result<I, Error>>> DatabaseFuture<I> for T {}
 
@@ -67,6 +213,32 @@ impl Helper {
         Self::map(self.executor.send(FindAll::new(query_builder)))
     }
 
+    /// Same as `find_all`, with a query for each chunk of `values` since
+    /// SQLite limits the number of values in a query.
+    fn find_all_chunked<V, F, Q, T>(
+        &mut self,
+        values: Vec<V>,
+        query_builder: F,
+    ) -> impl DatabaseFuture<Vec<T>>
+    where
+        V: 'static + Clone + Send,
+        F: 'static + Fn(Vec<V>) -> Q + Clone + Send,
+        Q: 'static + diesel::query_dsl::LoadQuery<diesel::SqliteConnection, T>,
+        T: 'static + Send,
+    {
+        let futures: Vec<_> = values
+            .chunks(MAX_IN_VALUES)
+            .map(|chunk| {
+                let chunk = chunk.to_vec();
+                let query_builder = query_builder.clone();
+
+                self.find_all(move || query_builder(chunk))
+            })
+            .collect();
+
+        future::try_join_all(futures).map_ok(|results| results.into_iter().flatten().collect())
+    }
+
     pub fn create_subscription(
         &mut self,
         new_subscription: NewSubscription,
@@ -119,6 +291,20 @@ impl Helper {
         )
     }
 
+    pub fn transform_category<F>(
+        &mut self,
+        name: String,
+        transform: F,
+    ) -> impl DatabaseFuture<Category>
+    where
+        F: FnOnce(&mut Category) + Send + 'static,
+    {
+        Self::map(
+            self.executor
+                .send(TransformCategory(name, Box::new(transform))),
+        )
+    }
+
     pub fn subscription_add_category(
         &mut self,
         subscription_id: Id,
@@ -151,19 +337,31 @@ impl Helper {
         )
     }
 
-    pub fn create_item(&mut self, new_item: NewItem) -> impl DatabaseFuture<Item> {
-        Self::map(self.executor.send(CreateItem(new_item)))
-    }
-
-    pub fn get_item(&mut self, id: Id) -> impl DatabaseFuture<Item> {
-        Self::map(self.executor.send(GetItem(id)))
+    /// Insert and update items in a single transaction.
+    ///
+    /// New items get the `extras` associated with their GUID, updated items
+    /// get their new tags and enclosures, and items in `mark_unread` are
+    /// marked as unread.
+    pub fn store_items(
+        &mut self,
+        new_items: Vec<NewItem>,
+        extras: HashMap<String, NewItemExtras>,
+        updates: Vec<(ItemUpdate, NewItemExtras)>,
+        mark_unread: Vec<Id>,
+    ) -> impl DatabaseFuture<()> {
+        Self::map(self.executor.send(StoreItems {
+            new_items,
+            extras,
+            updates,
+            mark_unread,
+        }))
     }
 
     pub fn get_items_and_subscriptions(
         &mut self,
         item_ids: Vec<Id>,
     ) -> impl DatabaseFuture<Vec<(Item, Subscription)>> {
-        self.find_all(move || {
+        self.find_all_chunked(item_ids, |item_ids| {
             use schema::items::dsl::*;
 
             items
@@ -172,50 +370,433 @@ impl Helper {
         })
     }
 
-    pub fn update_item(&mut self, item: Item) -> impl DatabaseFuture<Item> {
-        Self::map(self.executor.send(UpdateItem(item)))
+    pub fn get_subscription_items(
+        &mut self,
+        subscription_id_: Id,
+    ) -> impl DatabaseFuture<Vec<Item>> {
+        self.find_all(move || {
+            use schema::items::dsl::*;
+
+            items.filter(subscription_id.eq(subscription_id_))
+        })
+    }
+
+    pub fn get_items_enclosures(
+        &mut self,
+        item_ids: Vec<Id>,
+    ) -> impl DatabaseFuture<Vec<Enclosure>> {
+        self.find_all_chunked(item_ids, |item_ids| {
+            use schema::enclosures::dsl::*;
+
+            enclosures.filter(item_id.eq_any(item_ids)).order(id)
+        })
+    }
+
+    pub fn get_enclosure(&mut self, enclosure_id: Id) -> impl DatabaseFuture<Option<Enclosure>> {
+        self.find_all(move || {
+            use schema::enclosures::dsl::*;
+
+            enclosures.find(enclosure_id).limit(1)
+        })
+        .map_ok(|mut found| found.pop())
+    }
+
+    /// Get the enclosures of subscriptions with downloads enabled, and the
+    /// ones downloaded, with their item's publication date.
+    ///
+    /// Enclosures of the newest items come first.
+    pub fn get_downloadable_enclosures(
+        &mut self,
+    ) -> impl DatabaseFuture<Vec<(Enclosure, chrono::NaiveDateTime, Subscription)>> {
+        self.find_all(|| {
+            use schema::enclosures::dsl::*;
+            use schema::{items, subscriptions};
+
+            enclosures
+                .inner_join(items::table.inner_join(subscriptions::table))
+                .filter(
+                    subscriptions::download_enclosures
+                        .eq(true)
+                        .or(download_status.eq(Enclosure::DOWNLOADED)),
+                )
+                .order((items::published.desc(), id))
+                .select((
+                    schema::enclosures::all_columns,
+                    items::published,
+                    subscriptions::all_columns,
+                ))
+        })
+    }
+
+    pub fn update_enclosure(&mut self, enclosure: Enclosure) -> impl DatabaseFuture<Enclosure> {
+        Self::map(self.executor.send(UpdateEnclosure(enclosure)))
     }
 
+    pub fn get_favicon(&mut self, id: Id) -> impl DatabaseFuture<Option<Favicon>> {
+        self.find_all(move || {
+            use schema::favicons::dsl::*;
+
+            favicons.find(id).limit(1)
+        })
+        .map_ok(|mut found| found.pop())
+    }
+
+    pub fn set_favicon(&mut self, favicon: Favicon) -> impl DatabaseFuture<()> {
+        Self::map(self.executor.send(SetFavicon(favicon)))
+    }
+
+    /// Get the IDs of subscriptions with a favicon.
+    pub fn get_favicon_subscription_ids(&mut self) -> impl DatabaseFuture<Vec<Id>> {
+        self.find_all(|| {
+            use schema::favicons::dsl::*;
+
+            favicons.filter(data.is_not_null()).select(subscription_id)
+        })
+    }
+
+    pub fn update_items_content(&mut self, contents: Vec<(Id, String)>) -> impl DatabaseFuture<()> {
+        Self::map(self.executor.send(UpdateItemsContent(contents)))
+    }
+
+    /// Get the IDs of items with a raw content but no content.
+    pub fn get_items_to_reprocess(&mut self) -> impl DatabaseFuture<Vec<Id>> {
+        self.find_all(|| {
+            use schema::items::dsl::*;
+
+            items
+                .filter(content.eq(""))
+                .filter(raw_content.ne(""))
+                .select(id)
+                .order(id)
+        })
+    }
+
+    /// Get the newest items of a subscription whose full content wasn't fetched.
+    pub fn find_items_without_full_content(
+        &mut self,
+        subscription_id_: Id,
+        max_items: usize,
+    ) -> impl DatabaseFuture<Vec<Item>> {
+        self.find_all(move || {
+            use schema::items::dsl::*;
+
+            items
+                .filter(subscription_id.eq(subscription_id_))
+                .filter(full_content.is_null())
+                .order(published.desc())
+                .limit(max_items as i64)
+        })
+    }
+
+    pub fn set_item_full_content(
+        &mut self,
+        id: Id,
+        full_content: String,
+    ) -> impl DatabaseFuture<()> {
+        Self::map(self.executor.send(SetItemFullContent { id, full_content }))
+    }
+
+    pub fn delete_items(&mut self, ids: Vec<Id>) -> impl DatabaseFuture<usize> {
+        Self::map(self.executor.send(DeleteItems(ids)))
+    }
+
+    /// Find items matching `filter`, in the filter's order.
     pub fn find_items(
         &mut self,
-        read: Option<bool>,
-        starred: Option<bool>,
-        min_date: Option<chrono::NaiveDateTime>,
-        max_date: Option<chrono::NaiveDateTime>,
+        filter: ItemFilter,
         max_items: usize,
     ) -> impl DatabaseFuture<Vec<Item>> {
+        self.find_all(move || filter.into_sorted_query().limit(max_items as i64))
+    }
+
+    /// Find the IDs of items matching `filter`, in the filter's order.
+    pub fn find_item_ids(
+        &mut self,
+        filter: ItemFilter,
+        max_items: Option<usize>,
+    ) -> impl DatabaseFuture<Vec<Id>> {
         self.find_all(move || {
             use schema::items::dsl::*;
 
-            let mut query = items.into_boxed();
+            let query = filter.into_sorted_query().select(id);
 
-            if let Some(val) = read {
-                query = query.filter(is_read.eq(val));
+            match max_items {
+                Some(max_items) => query.limit(max_items as i64),
+                None => query,
             }
+        })
+    }
 
-            if let Some(val) = starred {
-                query = query.filter(is_starred.eq(val));
-            }
+    pub fn count_items(&mut self, filter: ItemFilter) -> impl DatabaseFuture<usize> {
+        self.find_all(move || filter.into_query().count())
+            .map_ok(|counts: Vec<i64>| counts.first().copied().unwrap_or(0) as usize)
+    }
 
-            if let Some(val) = min_date {
-                query = query.filter(published.ge(val));
-            }
+    /// Count unread items and get the newest one's date, by subscription.
+    pub fn count_unread_items(
+        &mut self,
+    ) -> impl DatabaseFuture<Vec<(Id, i64, Option<chrono::NaiveDateTime>)>> {
+        self.find_all(|| {
+            use diesel::dsl::{count_star, max};
+            use schema::items::dsl::*;
 
-            if let Some(val) = max_date {
-                query = query.filter(published.le(val));
-            }
+            items
+                .filter(is_read.eq(false))
+                .group_by(subscription_id)
+                .select((subscription_id, count_star(), max(published)))
+        })
+    }
 
-            query.limit(max_items as i64)
+    /// Find the versions of a subscription's items with a GUID in `guids`.
+    pub fn find_item_versions(
+        &mut self,
+        subscription_id_: Id,
+        guids: Vec<String>,
+    ) -> impl DatabaseFuture<Vec<ItemVersion>> {
+        self.find_all_chunked(guids, move |guids| {
+            use schema::items::dsl::*;
+
+            items
+                .filter(subscription_id.eq(subscription_id_))
+                .filter(guid.eq_any(guids))
+                .select((id, guid, updated))
         })
     }
 
-    pub fn get_subscription_items(
+    /// Find the GUIDs of a subscription's purged items in `guids`.
+    pub fn find_purged_items(
         &mut self,
         subscription_id_: Id,
-    ) -> impl DatabaseFuture<Vec<Item>> {
+        guids: Vec<String>,
+    ) -> impl DatabaseFuture<Vec<String>> {
+        self.find_all_chunked(guids, move |guids| {
+            use schema::purged_items::dsl::*;
+
+            purged_items
+                .filter(subscription_id.eq(subscription_id_))
+                .filter(guid.eq_any(guids))
+                .select(guid)
+        })
+    }
+
+    /// Forget the purged items of a subscription whose GUID isn't in `guids`.
+    pub fn forget_purged_items(
+        &mut self,
+        subscription_id: Id,
+        guids: Vec<String>,
+    ) -> impl DatabaseFuture<()> {
+        Self::map(self.executor.send(ForgetPurgedItems {
+            subscription_id,
+            guids,
+        }))
+    }
+
+    /// Delete items according to retention policies.
+    ///
+    /// Result is the IDs of the deleted items, or that would be if `dry_run`
+    /// is set.
+    pub fn purge_items(
+        &mut self,
+        default_policy: RetentionPolicy,
+        dry_run: bool,
+    ) -> impl DatabaseFuture<Vec<Id>> {
+        Self::map(self.executor.send(PurgeItems {
+            default_policy,
+            dry_run,
+        }))
+    }
+
+    pub fn update_items_state(
+        &mut self,
+        ids: Vec<Id>,
+        read: Option<bool>,
+        starred: Option<bool>,
+    ) -> impl DatabaseFuture<()> {
+        Self::map(self.executor.send(UpdateItemsState {
+            ids,
+            read,
+            starred,
+            kept_unread: None,
+            operation: None,
+        }))
+    }
+
+    /// Same as `update_items_state`, also changing whether items are kept
+    /// unread, and recording the changes as an operation which can be
+    /// reverted.
+    pub fn record_items_state(
+        &mut self,
+        operation: &str,
+        ids: Vec<Id>,
+        read: Option<bool>,
+        starred: Option<bool>,
+        kept_unread: Option<bool>,
+    ) -> impl DatabaseFuture<()> {
+        Self::map(self.executor.send(UpdateItemsState {
+            ids,
+            read,
+            starred,
+            kept_unread,
+            operation: Some(operation.to_owned()),
+        }))
+    }
+
+    /// Get the operations created after `min_date`, newest first.
+    pub fn get_operations(
+        &mut self,
+        min_date: chrono::NaiveDateTime,
+    ) -> impl DatabaseFuture<Vec<Operation>> {
         self.find_all(move || {
-            use schema::items::dsl::*;
-            items.filter(subscription_id.eq(subscription_id_))
+            use schema::operations::dsl::*;
+
+            operations.filter(created_at.ge(min_date)).order(id.desc())
+        })
+    }
+
+    /// Revert an operation created after `min_date`, see `RevertOperation`.
+    pub fn revert_operation(
+        &mut self,
+        id: Id,
+        min_date: chrono::NaiveDateTime,
+    ) -> impl DatabaseFuture<Option<Vec<Id>>> {
+        Self::map(self.executor.send(RevertOperation { id, min_date }))
+    }
+
+    pub fn purge_operations(
+        &mut self,
+        before: chrono::NaiveDateTime,
+    ) -> impl DatabaseFuture<usize> {
+        Self::map(self.executor.send(PurgeOperations(before)))
+    }
+
+    pub fn create_saved_search(
+        &mut self,
+        new_saved_search: NewSavedSearch,
+    ) -> impl DatabaseFuture<SavedSearch> {
+        Self::map(self.executor.send(CreateSavedSearch(new_saved_search)))
+    }
+
+    pub fn remove_saved_search(&mut self, name: String) -> impl DatabaseFuture<()> {
+        Self::map(self.executor.send(RemoveSavedSearch(name)))
+    }
+
+    pub fn get_saved_searches(&mut self) -> impl DatabaseFuture<Vec<SavedSearch>> {
+        self.find_all(|| {
+            use schema::saved_searches::dsl::*;
+
+            saved_searches.order(name)
+        })
+    }
+
+    pub fn get_saved_search(&mut self, name_: String) -> impl DatabaseFuture<Option<SavedSearch>> {
+        self.find_all(move || {
+            use schema::saved_searches::dsl::*;
+
+            saved_searches.filter(name.eq(name_)).limit(1)
+        })
+        .map_ok(|mut found| found.pop())
+    }
+
+    pub fn get_categories(&mut self) -> impl DatabaseFuture<Vec<Category>> {
+        self.find_all(|| {
+            use schema::categories::dsl::*;
+
+            categories.order(name)
+        })
+    }
+
+    /// Get all categories, with the IDs of their subscriptions.
+    pub fn get_categories_and_subscription_ids(
+        &mut self,
+    ) -> impl DatabaseFuture<Vec<(Category, Id)>> {
+        self.find_all(|| {
+            use schema::categories::dsl::*;
+            use schema::subscription_categories::dsl::{subscription_categories, subscription_id};
+
+            categories
+                .inner_join(subscription_categories)
+                .select((categories::all_columns(), subscription_id))
+                .order(name)
+        })
+    }
+
+    pub fn get_rules(&mut self) -> impl DatabaseFuture<Vec<Rule>> {
+        self.find_all(|| {
+            use schema::rules::dsl::*;
+
+            rules.order(name)
+        })
+    }
+
+    pub fn create_rule(&mut self, new_rule: NewRule) -> impl DatabaseFuture<Rule> {
+        Self::map(self.executor.send(CreateRule(new_rule)))
+    }
+
+    /// Remove a rule, the result is whether it existed.
+    pub fn remove_rule(&mut self, name: String) -> impl DatabaseFuture<bool> {
+        Self::map(self.executor.send(RemoveRule(name)))
+    }
+
+    pub fn add_item_labels(&mut self, labels: Vec<NewItemLabel>) -> impl DatabaseFuture<()> {
+        Self::map(self.executor.send(AddItemLabels(labels)))
+    }
+
+    pub fn remove_item_labels(&mut self, labels: Vec<NewItemLabel>) -> impl DatabaseFuture<()> {
+        Self::map(self.executor.send(RemoveItemLabels(labels)))
+    }
+
+    /// Get the changes recorded after `since`, oldest first.
+    pub fn get_changes(
+        &mut self,
+        since: i64,
+        max_changes: usize,
+    ) -> impl DatabaseFuture<Vec<Change>> {
+        self.find_all(move || {
+            use schema::changes::dsl::*;
+
+            changes
+                .filter(change_seq.gt(since))
+                .order(change_seq)
+                .limit(max_changes as i64)
+        })
+    }
+
+    pub fn get_items_labels(&mut self, item_ids: Vec<Id>) -> impl DatabaseFuture<Vec<ItemLabel>> {
+        self.find_all_chunked(item_ids, |item_ids| {
+            use schema::item_labels::dsl::*;
+
+            item_labels.filter(item_id.eq_any(item_ids)).order(name)
+        })
+    }
+
+    pub fn get_items_tags(&mut self, item_ids: Vec<Id>) -> impl DatabaseFuture<Vec<ItemTag>> {
+        self.find_all_chunked(item_ids, |item_ids| {
+            use schema::item_tags::dsl::*;
+
+            item_tags.filter(item_id.eq_any(item_ids)).order(name)
+        })
+    }
+
+    pub fn get_subscription_items_tags(
+        &mut self,
+        subscription_id: Id,
+    ) -> impl DatabaseFuture<Vec<ItemTag>> {
+        self.find_all(move || {
+            use schema::{item_tags, items};
+
+            item_tags::table
+                .inner_join(items::table)
+                .filter(items::subscription_id.eq(subscription_id))
+                .select(item_tags::all_columns)
+        })
+    }
+
+    /// Get the names of all labels added to items.
+    pub fn get_item_label_names(&mut self) -> impl DatabaseFuture<Vec<String>> {
+        self.find_all(|| {
+            use schema::item_labels::dsl::*;
+
+            item_labels.select(name).distinct().order(name)
         })
     }
 }
@@ -12,7 +12,7 @@ pub mod models;
 mod schema;
 
 pub use executor::Executor;
-pub use helper::{Error, Helper};
+pub use helper::{Error, Helper, ItemFilter, ItemOrder};
 
 
 #[derive(Debug, Clone, Copy, Deserialize, Serialize)]
@@ -1,10 +1,13 @@
 use serde::Serialize;
+use std::collections::HashSet;
 
 use super::schema::*;
+use crate::credentials::EncryptedCredentials;
 use crate::db;
 use crate::utils::make_url_absolute;
 
 #[derive(Debug, Clone, Serialize, Identifiable, AsChangeset, Queryable)]
+#[changeset_options(treat_none_as_null = "true")]
 pub struct Subscription {
     pub id: db::Id,
     pub feed_url: String,
@@ -12,6 +15,21 @@ pub struct Subscription {
     pub site_url: Option<String>,
     pub next_refresh: chrono::NaiveDateTime,
     pub error_count: i32,
+    /// Proxy overriding the global one, `"direct"` to disable proxying.
+    pub proxy: Option<String>,
+    #[serde(skip)]
+    pub credentials: Option<EncryptedCredentials>,
+    /// Retention overrides, see `RetentionPolicy`.
+    pub keep_read_days: Option<i32>,
+    pub keep_max_items: Option<i32>,
+    /// Extract the article from each item's page.
+    pub fetch_full_content: bool,
+    /// CSS selector of the article, instead of guessing it.
+    pub content_selector: Option<String>,
+    /// Download the enclosures of items, see `crate::downloader`.
+    pub download_enclosures: bool,
+    /// Number of most recent items whose downloads are kept.
+    pub keep_downloads: Option<i32>,
 }
 
 impl std::fmt::Display for Subscription {
@@ -28,6 +46,14 @@ pub struct NewSubscription {
     pub site_url: Option<String>,
     pub next_refresh: chrono::NaiveDateTime,
     pub error_count: i32,
+    pub proxy: Option<String>,
+    pub credentials: Option<EncryptedCredentials>,
+    pub keep_read_days: Option<i32>,
+    pub keep_max_items: Option<i32>,
+    pub fetch_full_content: bool,
+    pub content_selector: Option<String>,
+    pub download_enclosures: bool,
+    pub keep_downloads: Option<i32>,
 }
 
 impl NewSubscription {
@@ -61,15 +87,40 @@ impl NewSubscription {
             site_url,
             next_refresh,
             error_count: 0,
+            proxy: None,
+            credentials: None,
+            keep_read_days: None,
+            keep_max_items: None,
+            fetch_full_content: false,
+            content_selector: None,
+            download_enclosures: false,
+            keep_downloads: None,
         })
     }
 }
 
+/// Icon of a subscription's site.
+#[derive(Debug, Clone, Serialize, Identifiable, Insertable, Queryable)]
+#[primary_key(subscription_id)]
+pub struct Favicon {
+    pub subscription_id: db::Id,
+    /// Where the icon was found, `None` if it wasn't.
+    pub url: Option<String>,
+    pub content_type: Option<String>,
+    #[serde(skip)]
+    pub data: Option<Vec<u8>>,
+    pub fetched_at: chrono::NaiveDateTime,
+}
+
 #[derive(Debug, Clone, Serialize, Identifiable, AsChangeset, Queryable)]
 #[table_name = "categories"]
+#[changeset_options(treat_none_as_null = "true")]
 pub struct Category {
     pub id: db::Id,
     pub name: String,
+    /// Retention overrides, see `RetentionPolicy`.
+    pub keep_read_days: Option<i32>,
+    pub keep_max_items: Option<i32>,
 }
 
 #[derive(Debug, Insertable)]
@@ -104,6 +155,22 @@ pub struct Item {
     pub content: String,
     pub is_read: bool,
     pub is_starred: bool,
+    /// Entry ID from the feed, unique per subscription.
+    pub guid: String,
+    /// Content as found in the feed, `content` is sanitized.
+    #[serde(skip)]
+    pub raw_content: String,
+    /// `xml:base` of the content in the feed, if any.
+    #[serde(skip)]
+    pub content_base: Option<String>,
+    /// Sanitized article extracted from the item's page, empty if that failed.
+    pub full_content: Option<String>,
+    /// Whether the user explicitly kept the item unread.
+    pub is_kept_unread: bool,
+    /// When the item was marked read, if it is.
+    pub read_at: Option<chrono::NaiveDateTime>,
+    /// When the item was starred, if it is.
+    pub starred_at: Option<chrono::NaiveDateTime>,
 }
 
 #[derive(Debug, Insertable)]
@@ -118,6 +185,14 @@ pub struct NewItem {
     pub content: String,
     pub is_read: bool,
     pub is_starred: bool,
+    pub read_at: Option<chrono::NaiveDateTime>,
+    pub starred_at: Option<chrono::NaiveDateTime>,
+    /// Entry ID from the feed, unique per subscription.
+    pub guid: String,
+    /// Content as found in the feed, `content` is sanitized.
+    pub raw_content: String,
+    /// `xml:base` of the content in the feed, if any.
+    pub content_base: Option<String>,
 }
 
 impl NewItem {
@@ -156,6 +231,12 @@ impl NewItem {
 
         let url = make_url_absolute(url, &subscription.feed_url)?;
 
+        let guid = if entry.id.is_empty() {
+            url.clone()
+        } else {
+            entry.id.clone()
+        };
+
         Ok(Self {
             subscription_id: subscription.id,
             url,
@@ -163,9 +244,316 @@ impl NewItem {
             author,
             published,
             updated,
+            raw_content: content.clone(),
             content,
             is_read: false,
             is_starred: false,
+            read_at: None,
+            starred_at: None,
+            guid,
+            content_base: None,
         })
     }
 }
+
+#[derive(Debug, Clone, Serialize, Identifiable, AsChangeset, Queryable)]
+pub struct SavedSearch {
+    pub id: db::Id,
+    pub name: String,
+    /// Full-text search query.
+    pub query: String,
+}
+
+#[derive(Debug, Insertable)]
+#[table_name = "saved_searches"]
+pub struct NewSavedSearch {
+    pub name: String,
+    pub query: String,
+}
+
+/// An audio or video file attached to an item.
+#[derive(Debug, Clone, Serialize, Identifiable, AsChangeset, Queryable)]
+#[changeset_options(treat_none_as_null = "true")]
+pub struct Enclosure {
+    pub id: db::Id,
+    pub item_id: db::Id,
+    pub url: String,
+    pub mime_type: Option<String>,
+    /// In bytes.
+    pub length: Option<i64>,
+    /// In seconds.
+    pub duration: Option<i32>,
+    pub thumbnail_url: Option<String>,
+    /// See `crate::downloader`.
+    pub download_status: Option<String>,
+    pub download_attempts: i32,
+    /// In bytes.
+    pub download_size: Option<i64>,
+}
+
+impl Enclosure {
+    /// Values of `download_status`, which is `None` until downloaded.
+    pub const DOWNLOADED: &'static str = "downloaded";
+    pub const FAILED: &'static str = "failed";
+    /// Downloaded, then removed to stay within limits.
+    pub const REMOVED: &'static str = "removed";
+
+    pub fn is_downloaded(&self) -> bool {
+        self.download_status.as_deref() == Some(Self::DOWNLOADED)
+    }
+}
+
+/// An enclosure of an item that isn't stored yet, so without `item_id`.
+#[derive(Debug, Clone, Insertable, AsChangeset)]
+#[table_name = "enclosures"]
+#[changeset_options(treat_none_as_null = "true")]
+pub struct NewEnclosure {
+    pub url: String,
+    pub mime_type: Option<String>,
+    pub length: Option<i64>,
+    pub duration: Option<i32>,
+    pub thumbnail_url: Option<String>,
+}
+
+impl NewEnclosure {
+    /// Get the enclosures of an entry, from its media objects and its
+    /// enclosure links.
+    pub fn from_entry(entry: &feed_rs::model::Entry, subscription: &Subscription) -> Vec<Self> {
+        let absolute = |url: &str| make_url_absolute(url.trim(), &subscription.feed_url).ok();
+
+        let mut enclosures = Vec::new();
+
+        for media in &entry.media {
+            let thumbnail_url = media
+                .thumbnails
+                .first()
+                .and_then(|thumbnail| absolute(&thumbnail.image.uri));
+
+            for content in &media.content {
+                let url = match content.url.as_ref().and_then(|url| absolute(url.as_str())) {
+                    Some(url) => url,
+                    None => continue,
+                };
+
+                enclosures.push(Self {
+                    url,
+                    mime_type: content.content_type.as_ref().map(|t| t.to_string()),
+                    length: content.size.map(|size| size as i64),
+                    duration: content
+                        .duration
+                        .or(media.duration)
+                        .map(|duration| duration.as_secs() as i32),
+                    thumbnail_url: thumbnail_url.clone(),
+                });
+            }
+        }
+
+        let links = entry
+            .links
+            .iter()
+            .filter(|link| link.rel.as_deref() == Some("enclosure"));
+        for link in links {
+            if let Some(url) = absolute(&link.href) {
+                enclosures.push(Self {
+                    url,
+                    mime_type: link.media_type.clone(),
+                    length: link.length.map(|length| length as i64),
+                    duration: None,
+                    thumbnail_url: None,
+                });
+            }
+        }
+
+        // RSS enclosures can also be listed as media objects
+        let mut seen = HashSet::new();
+        enclosures.retain(|enclosure| seen.insert(enclosure.url.clone()));
+
+        enclosures
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Queryable)]
+pub struct ItemLabel {
+    pub item_id: db::Id,
+    pub name: String,
+}
+
+#[derive(Debug, Insertable)]
+#[table_name = "item_labels"]
+pub struct NewItemLabel {
+    pub item_id: db::Id,
+    pub name: String,
+}
+
+/// A category given by the feed to an item.
+#[derive(Debug, Clone, Serialize, Queryable)]
+pub struct ItemTag {
+    pub item_id: db::Id,
+    pub name: String,
+}
+
+#[derive(Debug, Insertable)]
+#[table_name = "item_tags"]
+pub struct NewItemTag {
+    pub item_id: db::Id,
+    pub name: String,
+}
+
+/// What is stored with a new item in other tables.
+#[derive(Debug, Default)]
+pub struct NewItemExtras {
+    pub labels: Vec<String>,
+    pub tags: Vec<String>,
+    pub enclosures: Vec<NewEnclosure>,
+}
+
+/// A filter rule, see `crate::rules`.
+#[derive(Debug, Clone, Serialize, Identifiable, Queryable)]
+pub struct Rule {
+    pub id: db::Id,
+    pub name: String,
+    pub field: String,
+    pub pattern: String,
+    pub is_regex: bool,
+    /// Only apply to items of this subscription.
+    pub subscription_id: Option<db::Id>,
+    /// Only apply to items of subscriptions in this category.
+    pub category: Option<String>,
+    pub action: String,
+    /// Label added by the `label` action.
+    pub label: Option<String>,
+}
+
+#[derive(Debug, Insertable)]
+#[table_name = "rules"]
+pub struct NewRule {
+    pub name: String,
+    pub field: String,
+    pub pattern: String,
+    pub is_regex: bool,
+    pub subscription_id: Option<db::Id>,
+    pub category: Option<String>,
+    pub action: String,
+    pub label: Option<String>,
+}
+
+/// Entry of an item which was purged, see `PurgeItems`.
+#[derive(Debug, Clone, Queryable, Insertable)]
+#[table_name = "purged_items"]
+pub struct PurgedItem {
+    pub subscription_id: db::Id,
+    pub guid: String,
+}
+
+/// Identity and version of a stored item.
+#[derive(Debug, Clone, Queryable)]
+pub struct ItemVersion {
+    pub id: db::Id,
+    pub guid: String,
+    pub updated: chrono::NaiveDateTime,
+}
+
+/// New content for an existing item.
+#[derive(Debug, Identifiable, AsChangeset)]
+#[table_name = "items"]
+#[changeset_options(treat_none_as_null = "true")]
+pub struct ItemUpdate {
+    pub id: db::Id,
+    pub url: String,
+    pub title: String,
+    pub author: Option<String>,
+    pub updated: chrono::NaiveDateTime,
+    pub content: String,
+    pub guid: String,
+    pub raw_content: String,
+    pub content_base: Option<String>,
+}
+
+impl ItemUpdate {
+    pub fn new(id: db::Id, item: NewItem) -> Self {
+        Self {
+            id,
+            url: item.url,
+            title: item.title,
+            author: item.author,
+            updated: item.updated,
+            content: item.content,
+            guid: item.guid,
+            raw_content: item.raw_content,
+            content_base: item.content_base,
+        }
+    }
+}
+
+/// A change of the state of items, which can be reverted.
+#[derive(Debug, Clone, Serialize, Identifiable, Queryable)]
+pub struct Operation {
+    pub id: db::Id,
+    /// What made the change, e.g. `mark-all-as-read`.
+    pub kind: String,
+    pub created_at: chrono::NaiveDateTime,
+    /// New read state of the items, if changed.
+    pub is_read: Option<bool>,
+    /// New starred state of the items, if changed.
+    pub is_starred: Option<bool>,
+    /// New kept unread state of the items, if changed.
+    pub is_kept_unread: Option<bool>,
+    /// Number of items actually changed.
+    pub item_count: i32,
+    pub is_reverted: bool,
+}
+
+impl Operation {
+    /// Creation date of the oldest operations which can still be reverted.
+    pub fn min_date(undo_window: std::time::Duration) -> chrono::NaiveDateTime {
+        let undo_window = chrono::Duration::from_std(undo_window)
+            .unwrap_or_else(|_| chrono::Duration::max_value());
+
+        chrono::Utc::now()
+            .naive_utc()
+            .checked_sub_signed(undo_window)
+            .unwrap_or(chrono::naive::MIN_DATETIME)
+    }
+}
+
+#[derive(Debug, Insertable)]
+#[table_name = "operations"]
+pub struct NewOperation {
+    pub kind: String,
+    pub created_at: chrono::NaiveDateTime,
+    pub is_read: Option<bool>,
+    pub is_starred: Option<bool>,
+    pub is_kept_unread: Option<bool>,
+    pub item_count: i32,
+}
+
+/// State of an item before an operation changed it.
+#[derive(Debug, Clone, Queryable, Insertable)]
+#[table_name = "operation_items"]
+pub struct OperationItem {
+    pub operation_id: db::Id,
+    pub item_id: db::Id,
+    pub was_read: bool,
+    pub was_starred: bool,
+    pub was_kept_unread: bool,
+    pub read_at: Option<chrono::NaiveDateTime>,
+    pub starred_at: Option<chrono::NaiveDateTime>,
+}
+
+/// Latest change of an object, changes are recorded by triggers.
+#[derive(Debug, Clone, Queryable)]
+pub struct Change {
+    /// Increases with each change, across all objects.
+    pub change_seq: i64,
+    pub kind: String,
+    pub object_id: db::Id,
+    /// Name of the category, for categories.
+    pub name: Option<String>,
+    pub is_deleted: bool,
+}
+
+impl Change {
+    pub const ITEM: &'static str = "item";
+    pub const SUBSCRIPTION: &'static str = "subscription";
+    pub const CATEGORY: &'static str = "category";
+}
@@ -2,6 +2,57 @@ table! {
     categories (id) {
         id -> Integer,
         name -> Text,
+        keep_read_days -> Nullable<Integer>,
+        keep_max_items -> Nullable<Integer>,
+    }
+}
+
+table! {
+    changes (change_seq) {
+        change_seq -> BigInt,
+        kind -> Text,
+        object_id -> Integer,
+        name -> Nullable<Text>,
+        is_deleted -> Bool,
+    }
+}
+
+table! {
+    enclosures (id) {
+        id -> Integer,
+        item_id -> Integer,
+        url -> Text,
+        mime_type -> Nullable<Text>,
+        length -> Nullable<BigInt>,
+        duration -> Nullable<Integer>,
+        thumbnail_url -> Nullable<Text>,
+        download_status -> Nullable<Text>,
+        download_attempts -> Integer,
+        download_size -> Nullable<BigInt>,
+    }
+}
+
+table! {
+    favicons (subscription_id) {
+        subscription_id -> Integer,
+        url -> Nullable<Text>,
+        content_type -> Nullable<Text>,
+        data -> Nullable<Binary>,
+        fetched_at -> Timestamp,
+    }
+}
+
+table! {
+    item_labels (item_id, name) {
+        item_id -> Integer,
+        name -> Text,
+    }
+}
+
+table! {
+    item_tags (item_id, name) {
+        item_id -> Integer,
+        name -> Text,
     }
 }
 
@@ -17,6 +68,76 @@ table! {
         content -> Text,
         is_read -> Bool,
         is_starred -> Bool,
+        guid -> Text,
+        raw_content -> Text,
+        content_base -> Nullable<Text>,
+        full_content -> Nullable<Text>,
+        is_kept_unread -> Bool,
+        read_at -> Nullable<Timestamp>,
+        starred_at -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    items_fts (rowid) {
+        rowid -> Integer,
+        title -> Text,
+        author -> Nullable<Text>,
+        content -> Text,
+    }
+}
+
+table! {
+    operation_items (operation_id, item_id) {
+        operation_id -> Integer,
+        item_id -> Integer,
+        was_read -> Bool,
+        was_starred -> Bool,
+        was_kept_unread -> Bool,
+        read_at -> Nullable<Timestamp>,
+        starred_at -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    operations (id) {
+        id -> Integer,
+        kind -> Text,
+        created_at -> Timestamp,
+        is_read -> Nullable<Bool>,
+        is_starred -> Nullable<Bool>,
+        is_kept_unread -> Nullable<Bool>,
+        item_count -> Integer,
+        is_reverted -> Bool,
+    }
+}
+
+table! {
+    purged_items (subscription_id, guid) {
+        subscription_id -> Integer,
+        guid -> Text,
+    }
+}
+
+table! {
+    rules (id) {
+        id -> Integer,
+        name -> Text,
+        field -> Text,
+        pattern -> Text,
+        is_regex -> Bool,
+        subscription_id -> Nullable<Integer>,
+        category -> Nullable<Text>,
+        action -> Text,
+        label -> Nullable<Text>,
+    }
+}
+
+table! {
+    saved_searches (id) {
+        id -> Integer,
+        name -> Text,
+        query -> Text,
     }
 }
 
@@ -35,16 +156,43 @@ table! {
         site_url -> Nullable<Text>,
         next_refresh -> Timestamp,
         error_count -> Integer,
+        proxy -> Nullable<Text>,
+        credentials -> Nullable<Binary>,
+        keep_read_days -> Nullable<Integer>,
+        keep_max_items -> Nullable<Integer>,
+        fetch_full_content -> Bool,
+        content_selector -> Nullable<Text>,
+        download_enclosures -> Bool,
+        keep_downloads -> Nullable<Integer>,
     }
 }
 
+joinable!(enclosures -> items (item_id));
+joinable!(favicons -> subscriptions (subscription_id));
+joinable!(item_labels -> items (item_id));
+joinable!(item_tags -> items (item_id));
 joinable!(items -> subscriptions (subscription_id));
+joinable!(operation_items -> items (item_id));
+joinable!(operation_items -> operations (operation_id));
+joinable!(purged_items -> subscriptions (subscription_id));
+joinable!(rules -> subscriptions (subscription_id));
 joinable!(subscription_categories -> categories (category_id));
 joinable!(subscription_categories -> subscriptions (subscription_id));
 
 allow_tables_to_appear_in_same_query!(
     categories,
+    changes,
+    enclosures,
+    favicons,
+    item_labels,
+    item_tags,
     items,
+    items_fts,
+    operation_items,
+    operations,
+    purged_items,
+    rules,
+    saved_searches,
     subscription_categories,
     subscriptions,
 );
@@ -0,0 +1,319 @@
+use actix::prelude::*;
+use actix_web::web;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use crate::db::models::{Enclosure, Subscription};
+use crate::feed_manager::FeedManager;
+use crate::prelude::*;
+
+/// Failed downloads are retried on each run, until this many attempts.
+const MAX_ATTEMPTS: i32 = 5;
+
+/// Actor that periodically downloads the enclosures of subscriptions with
+/// downloads enabled, so clients can get them from freader.
+///
+/// Only the enclosures of each subscription's `keep_downloads` most recent
+/// items are kept, and the oldest ones are removed to stay within the quota.
+pub struct Downloader {
+    db: db::Helper,
+    feed_manager: FeedManager,
+    dir: PathBuf,
+    quota: u64,
+    /// Downloads can take longer than the interval between runs.
+    running: bool,
+}
+
+impl Downloader {
+    pub fn new(cfg: &Config, db: db::Helper, feed_manager: FeedManager) -> Self {
+        Downloader {
+            db,
+            feed_manager,
+            dir: PathBuf::from(&cfg.download_dir),
+            quota: cfg.download_quota,
+            running: false,
+        }
+    }
+
+    /// Get the path of a downloaded enclosure.
+    pub fn path(cfg: &Config, enclosure: &Enclosure) -> PathBuf {
+        file_path(Path::new(&cfg.download_dir), enclosure)
+    }
+
+    fn download(&mut self, ctx: &mut <Self as Actor>::Context) {
+        ctx.notify(DownloadEnclosures);
+    }
+}
+
+impl Actor for Downloader {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        // Download now, and every 15 minutes
+        self.download(ctx);
+        ctx.run_interval(std::time::Duration::from_secs(15 * 60), Self::download);
+    }
+}
+
+fn file_path(dir: &Path, enclosure: &Enclosure) -> PathBuf {
+    dir.join(enclosure.id.inner().to_string())
+}
+
+/// Download missing enclosures, and remove the ones not wanted anymore.
+struct DownloadEnclosures;
+
+impl Message for DownloadEnclosures {
+    type Result = Result<(), ()>;
+}
+
+impl Handler<DownloadEnclosures> for Downloader {
+    type Result = ResponseActFuture<Self, <DownloadEnclosures as Message>::Result>;
+
+    fn handle(&mut self, _: DownloadEnclosures, _: &mut Self::Context) -> Self::Result {
+        if self.running {
+            log::debug!("Enclosures are still being downloaded");
+            return Box::pin(actix::fut::ok(()));
+        }
+        self.running = true;
+
+        let run = Run {
+            db: self.db.clone(),
+            feed_manager: self.feed_manager.clone(),
+            dir: self.dir.clone(),
+            quota: self.quota,
+        };
+
+        Box::pin(
+            actix::fut::wrap_future(run.run()).map(|result, act: &mut Self, _| {
+                act.running = false;
+                result
+            }),
+        )
+    }
+}
+
+
+/// A single run of the downloader.
+struct Run {
+    db: db::Helper,
+    feed_manager: FeedManager,
+    dir: PathBuf,
+    quota: u64,
+}
+
+impl Run {
+    async fn run(mut self) -> Result<(), ()> {
+        let enclosures = self.db.get_downloadable_enclosures().await.map_err(|e| {
+            log::error!("Could not load enclosures from db: {}", e);
+        })?;
+
+        // Enclosures come newest first, so the first ones of each
+        // subscription are the ones to keep
+        let mut counts = HashMap::new();
+        let mut wanted = Vec::new();
+        let mut unwanted = Vec::new();
+        for (enclosure, published, subscription) in enclosures {
+            let count = counts.entry(subscription.id).or_insert(0);
+            *count += 1;
+
+            let is_wanted = subscription.download_enclosures
+                && subscription
+                    .keep_downloads
+                    .map_or(true, |keep| *count <= keep);
+
+            if is_wanted {
+                wanted.push((enclosure, published, subscription));
+            } else if enclosure.is_downloaded() {
+                unwanted.push(enclosure);
+            }
+        }
+
+        for enclosure in unwanted {
+            self.remove(enclosure).await?;
+        }
+
+        // Downloaded enclosures, newest first
+        let mut downloaded: Vec<(chrono::NaiveDateTime, Enclosure)> = wanted
+            .iter()
+            .filter(|(enclosure, _, _)| enclosure.is_downloaded())
+            .map(|(enclosure, published, _)| (*published, enclosure.clone()))
+            .collect();
+
+        let dir = self.dir.clone();
+        let known: HashSet<PathBuf> = downloaded
+            .iter()
+            .map(|(_, enclosure)| file_path(&self.dir, enclosure))
+            .collect();
+        let _ = web::block(move || {
+            remove_unknown_files(&dir, &known);
+            Ok::<_, ()>(())
+        })
+        .await;
+
+        let pending = wanted.into_iter().filter(|(enclosure, _, _)| {
+            enclosure.download_status.is_none() && enclosure.download_attempts < MAX_ATTEMPTS
+        });
+
+        let mut count = 0;
+
+        for (enclosure, published, subscription) in pending {
+            // Downloads of older items are removed to make room
+            let newer_size: u64 = downloaded
+                .iter()
+                .filter(|(other_published, _)| *other_published >= published)
+                .map(|(_, other)| download_size(other))
+                .sum();
+
+            let max_size = self.quota.saturating_sub(newer_size);
+            if max_size == 0 {
+                log::info!("Download quota reached");
+                break;
+            }
+
+            let enclosure = self.download(enclosure, &subscription, max_size).await?;
+            if !enclosure.is_downloaded() {
+                continue;
+            }
+
+            count += 1;
+
+            let index = downloaded
+                .iter()
+                .position(|(other_published, _)| *other_published < published)
+                .unwrap_or_else(|| downloaded.len());
+            downloaded.insert(index, (published, enclosure));
+
+            let mut usage: u64 = downloaded
+                .iter()
+                .map(|(_, other)| download_size(other))
+                .sum();
+            while usage > self.quota {
+                let (_, oldest) = downloaded.pop().expect("usage is over 0");
+                usage -= download_size(&oldest);
+                self.remove(oldest).await?;
+            }
+        }
+
+        if count > 0 {
+            log::info!("Downloaded {} enclosures", count);
+        }
+
+        Ok(())
+    }
+
+    /// Download an enclosure, the result is its updated version.
+    async fn download(
+        &mut self,
+        mut enclosure: Enclosure,
+        subscription: &Subscription,
+        max_size: u64,
+    ) -> Result<Enclosure, ()> {
+        let dir = self.dir.clone();
+        if let Err(e) = web::block(move || std::fs::create_dir_all(dir)).await {
+            log::error!("Could not create {}: {}", self.dir.display(), e);
+            return Err(());
+        }
+
+        log::debug!("Downloading {}", enclosure.url);
+
+        let path = file_path(&self.dir, &enclosure);
+        let partial_path = path.with_extension("part");
+
+        let result = match self
+            .feed_manager
+            .download_enclosure(&enclosure, subscription, &partial_path, max_size)
+            .await
+        {
+            Ok(size) => {
+                let (from, to) = (partial_path.clone(), path);
+                web::block(move || std::fs::rename(from, to))
+                    .await
+                    .map(|()| size)
+                    .map_err(|e| {
+                        log::error!("Could not move {}: {}", partial_path.display(), e);
+                        "Could not write enclosure."
+                    })
+            }
+            Err(e) => Err(e),
+        };
+
+        match result {
+            Ok(size) => {
+                enclosure.download_status = Some(Enclosure::DOWNLOADED.to_owned());
+                enclosure.download_size = Some(size as i64);
+            }
+            Err(e) => {
+                log::warn!("Could not download {}: {}", enclosure.url, e);
+                let _ = web::block(move || std::fs::remove_file(partial_path)).await;
+
+                enclosure.download_attempts += 1;
+                if enclosure.download_attempts >= MAX_ATTEMPTS {
+                    enclosure.download_status = Some(Enclosure::FAILED.to_owned());
+                }
+            }
+        }
+
+        self.db.update_enclosure(enclosure).await.map_err(|e| {
+            log::error!("Could not update enclosure: {}", e);
+        })
+    }
+
+    /// Remove a downloaded enclosure.
+    async fn remove(&mut self, mut enclosure: Enclosure) -> Result<(), ()> {
+        log::debug!("Removing download of {}", enclosure.url);
+
+        let path = file_path(&self.dir, &enclosure);
+        let result = {
+            let path = path.clone();
+            web::block(move || std::fs::remove_file(path)).await
+        };
+        match result {
+            Err(web::BlockingError::Error(e)) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => log::error!("Could not remove {}: {}", path.display(), e),
+            Ok(()) => {}
+        }
+
+        enclosure.download_status = Some(Enclosure::REMOVED.to_owned());
+        enclosure.download_size = None;
+
+        self.db
+            .update_enclosure(enclosure)
+            .await
+            .map(|_| ())
+            .map_err(|e| {
+                log::error!("Could not update enclosure: {}", e);
+            })
+    }
+}
+
+/// Remove files left by deleted items and interrupted downloads.
+///
+/// Only files named like downloads are removed, in case the directory is
+/// shared.
+fn remove_unknown_files(dir: &Path, known: &HashSet<PathBuf>) {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+
+    for entry in entries.filter_map(Result::ok) {
+        let path = entry.path();
+        let is_download = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .map_or(false, |name| {
+                name.trim_end_matches(".part").parse::<i32>().is_ok()
+            });
+
+        if is_download && path.is_file() && !known.contains(&path) {
+            log::debug!("Removing unknown download {}", path.display());
+            if let Err(e) = std::fs::remove_file(&path) {
+                log::error!("Could not remove {}: {}", path.display(), e);
+            }
+        }
+    }
+}
+
+fn download_size(enclosure: &Enclosure) -> u64 {
+    enclosure.download_size.unwrap_or(0) as u64
+}
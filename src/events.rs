@@ -0,0 +1,50 @@
+use tokio::sync::broadcast;
+
+use crate::prelude::*;
+
+/// Events kept for receivers which are behind, older ones are dropped.
+const CAPACITY: usize = 256;
+
+/// Something that happened, which clients may want to know about live.
+#[derive(Debug, Clone)]
+pub enum Event {
+    /// New items were stored for a subscription.
+    NewItems {
+        subscription_id: db::Id,
+        count: usize,
+    },
+    /// The state or labels of items changed, or they were deleted.
+    ItemsChanged(Vec<db::Id>),
+    SubscriptionAdded(db::Id),
+    SubscriptionRemoved(db::Id),
+}
+
+/// Broadcasts events to all current receivers.
+#[derive(Clone)]
+pub struct Events {
+    sender: broadcast::Sender<Event>,
+}
+
+impl Events {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(CAPACITY);
+
+        Events { sender }
+    }
+
+    /// Send an event, it is lost if nobody is listening.
+    pub fn send(&self, event: Event) {
+        let _ = self.sender.send(event);
+    }
+
+    /// Receive the events sent from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.sender.subscribe()
+    }
+}
+
+impl Default for Events {
+    fn default() -> Self {
+        Self::new()
+    }
+}
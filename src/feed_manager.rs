@@ -1,29 +1,352 @@
+use actix_web::web;
 use feed_rs::model::{Entry, Feed};
-use futures::TryFutureExt;
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
-use crate::db::models::{NewItem, NewSubscription, Subscription};
+use crate::credentials::{Cipher, Credentials, EncryptedCredentials};
+use crate::db::models::{
+    Enclosure, Favicon, Item, ItemUpdate, NewEnclosure, NewItem, NewItemExtras, NewSubscription,
+    Subscription,
+};
+use crate::events::{Event, Events};
+use crate::html::{self, FeedLink, Sanitizer};
 use crate::prelude::*;
+use crate::resolvers;
+use crate::rules::{RuleSet, Target};
 use crate::updater::Updater;
+use crate::utils::{make_url_absolute, redact_url};
+use crate::xml_base;
+
+/// Proxy value that disables proxying for a subscription.
+pub const NO_PROXY: &str = "direct";
+
+/// Maximum number of item pages downloaded per refresh, for full contents.
+const MAX_FULL_CONTENTS_PER_REFRESH: usize = 10;
+
+/// Favicons are looked for again after this many days.
+const FAVICON_REFRESH_DAYS: i64 = 7;
+
+/// Maximum size of a favicon, in bytes.
+const FAVICON_MAX_SIZE: usize = 1024 * 1024;
+
+/// Size of the data written at once when downloading enclosures.
+const WRITE_BUFFER_SIZE: usize = 1024 * 1024;
+
+/// Maximum number of redirects followed, same as reqwest's default.
+pub const MAX_REDIRECTS: usize = 10;
+
+/// A successful subscription.
+#[derive(Debug)]
+pub struct Subscribed {
+    pub subscription: Subscription,
+    /// Feeds found when the subscribed URL was a web page.
+    ///
+    /// The subscription is to the first one.
+    pub candidates: Vec<FeedLink>,
+}
+
+/// Body and content type of a response.
+struct Download {
+    body: Vec<u8>,
+    content_type: Option<String>,
+}
+
+impl Download {
+    fn is_html(&self) -> bool {
+        match &self.content_type {
+            Some(content_type) => content_type.starts_with("text/html"),
+            None => html::looks_like_html(&self.body),
+        }
+    }
+}
+
+/// A feed, with what `feed_rs` doesn't keep.
+struct ParsedFeed {
+    feed: Feed,
+    /// `xml:base` of each entry's content, see `xml_base::content_bases`.
+    content_bases: Vec<Option<String>>,
+}
+
+/// Subscription specific settings used when fetching a feed.
+#[derive(Debug, Clone, Default)]
+pub struct FetchOptions {
+    pub proxy: Option<String>,
+    pub credentials: Option<Credentials>,
+}
 
 #[derive(Clone)]
 pub struct FeedManager {
+    cfg: Config,
     db: db::Helper,
+    events: Events,
+    cipher: Option<Cipher>,
+    sanitizer: Sanitizer,
     http_client: reqwest::Client,
+    /// Clients not using the global proxy or not following redirects,
+    /// by proxy URL and whether they follow redirects.
+    proxied_clients: Arc<Mutex<HashMap<(String, bool), reqwest::Client>>>,
+    /// Subscriptions whose favicon is being looked for.
+    pending_favicons: Arc<Mutex<HashSet<db::Id>>>,
 }
 
 impl FeedManager {
-    pub fn new(db: db::Helper) -> Self {
-        FeedManager {
+    pub fn new(cfg: &Config, db: db::Helper, events: Events) -> reqwest::Result<Self> {
+        let http_client = Self::build_client(cfg, cfg.proxy.as_deref(), true)?;
+
+        Ok(FeedManager {
+            cfg: cfg.clone(),
             db,
-            http_client: reqwest::Client::new(),
+            events,
+            cipher: cfg.secret_key.as_deref().map(Cipher::new),
+            sanitizer: Sanitizer::new(&cfg.iframe_hosts),
+            http_client,
+            proxied_clients: Default::default(),
+            pending_favicons: Default::default(),
+        })
+    }
+
+    pub fn build_client(
+        cfg: &Config,
+        proxy: Option<&str>,
+        follow_redirects: bool,
+    ) -> reqwest::Result<reqwest::Client> {
+        let redirect_policy = if follow_redirects {
+            reqwest::redirect::Policy::limited(MAX_REDIRECTS)
+        } else {
+            reqwest::redirect::Policy::none()
+        };
+
+        let mut builder = reqwest::Client::builder()
+            .connect_timeout(cfg.fetch_connect_timeout)
+            .timeout(cfg.fetch_timeout)
+            .redirect(redirect_policy)
+            .user_agent(cfg.user_agent.as_str());
+
+        if let Some(proxy) = proxy {
+            builder = builder.proxy(reqwest::Proxy::all(proxy)?);
+        }
+
+        builder.build()
+    }
+
+    /// Read the next chunk of a response, waiting at most `read_timeout`
+    /// for it.
+    ///
+    /// The client's timeout applies to the whole response, this one
+    /// detects stalled connections sooner.
+    pub async fn read_chunk(
+        resp: &mut reqwest::Response,
+        read_timeout: Duration,
+    ) -> Result<Option<web::Bytes>, String> {
+        match tokio::time::timeout(read_timeout, resp.chunk()).await {
+            Ok(chunk) => chunk.map_err(|e| e.to_string()),
+            Err(_) => Err(format!("Timed out reading {}", resp.url())),
+        }
+    }
+
+    /// Ensure `proxy` can be used as a subscription's proxy.
+    pub fn check_proxy(proxy: &str) -> Result<(), &'static str> {
+        if proxy == NO_PROXY {
+            return Ok(());
+        }
+
+        reqwest::Proxy::all(proxy)
+            .map(|_| ())
+            .map_err(|_| "Invalid proxy URL.")
+    }
+
+    pub fn encrypt_credentials(
+        &self,
+        credentials: &Credentials,
+    ) -> Result<EncryptedCredentials, &'static str> {
+        self.cipher
+            .as_ref()
+            .ok_or("FREADER_SECRET_KEY must be set to store credentials.")?
+            .encrypt(credentials)
+    }
+
+    fn fetch_options(&self, subscription: &Subscription) -> Result<FetchOptions, &'static str> {
+        let credentials = match &subscription.credentials {
+            Some(encrypted) => Some(
+                self.cipher
+                    .as_ref()
+                    .ok_or("FREADER_SECRET_KEY must be set to use credentials.")?
+                    .decrypt(encrypted)?,
+            ),
+            None => None,
+        };
+
+        Ok(FetchOptions {
+            proxy: subscription.proxy.clone(),
+            credentials,
+        })
+    }
+
+    /// Get the options to fetch `url`, which is linked from the subscription's
+    /// feed.
+    ///
+    /// Credentials are only kept for the feed's host, so they don't leak to
+    /// third parties.
+    fn linked_fetch_options(
+        &self,
+        url: &str,
+        subscription: &Subscription,
+    ) -> Result<FetchOptions, &'static str> {
+        let options = self.fetch_options(subscription)?;
+
+        Ok(Self::options_for_host(url, &subscription.feed_url, options))
+    }
+
+    /// Get the options to fetch `url`, which was found from `origin`.
+    ///
+    /// Credentials are removed if `url` isn't on the same host as `origin`.
+    fn options_for_host(url: &str, origin: &str, mut options: FetchOptions) -> FetchOptions {
+        let host = |url: &str| {
+            reqwest::Url::parse(url)
+                .ok()
+                .and_then(|url| url.host_str().map(str::to_lowercase))
+        };
+        if host(url).is_none() || host(url) != host(origin) {
+            options.credentials = None;
+        }
+
+        options
+    }
+
+    /// Get the client to use for fetching `url`.
+    ///
+    /// The subscription's proxy has priority, then `.onion` URLs use
+    /// the onion proxy, and anything else the global proxy.
+    fn client_for(
+        &self,
+        url: &str,
+        options: &FetchOptions,
+        follow_redirects: bool,
+    ) -> Result<reqwest::Client, &'static str> {
+        let is_onion = reqwest::Url::parse(url)
+            .ok()
+            .and_then(|url| url.host_str().map(|host| host.ends_with(".onion")))
+            .unwrap_or(false);
+
+        let global_proxy = self.cfg.proxy.as_deref();
+        let proxy = match options.proxy.as_deref() {
+            Some(NO_PROXY) => None,
+            Some(proxy) => Some(proxy),
+            None if is_onion => self.cfg.onion_proxy.as_deref().or(global_proxy),
+            None => global_proxy,
+        };
+
+        // The default client already uses the global proxy
+        if proxy == global_proxy && follow_redirects {
+            return Ok(self.http_client.clone());
+        }
+
+        let key = proxy.unwrap_or(NO_PROXY);
+
+        let mut clients = self.proxied_clients.lock().unwrap();
+        if let Some(client) = clients.get(&(key.to_owned(), follow_redirects)) {
+            return Ok(client.clone());
+        }
+
+        let client = Self::build_client(&self.cfg, proxy, follow_redirects).map_err(|e| {
+            log::error!(
+                "Could not create HTTP client for proxy {}: {}",
+                redact_url(key),
+                e
+            );
+            "Invalid proxy."
+        })?;
+        clients.insert((key.to_owned(), follow_redirects), client.clone());
+
+        Ok(client)
+    }
+
+    /// Send a GET request for `url`, with the subscription's options.
+    ///
+    /// Redirects of requests with credentials are followed here instead of
+    /// by reqwest, which would keep custom headers on other hosts: the
+    /// credentials are dropped as soon as the origin changes.
+    async fn send(
+        &self,
+        url: &str,
+        options: &FetchOptions,
+        timeout: Option<Duration>,
+    ) -> Result<reqwest::Response, String> {
+        let mut url = url.to_owned();
+        let mut credentials = options.credentials.as_ref();
+
+        for _ in 0..=MAX_REDIRECTS {
+            let client = self.client_for(&url, options, credentials.is_none())?;
+
+            let mut request = client.get(&url);
+            if let Some(timeout) = timeout {
+                request = request.timeout(timeout);
+            }
+            if let Some(credentials) = credentials {
+                request = credentials.apply(request);
+            }
+
+            let resp = request.send().await.map_err(|e| e.to_string())?;
+            if credentials.is_none() || !resp.status().is_redirection() {
+                return Ok(resp);
+            }
+
+            let next = resp
+                .headers()
+                .get(reqwest::header::LOCATION)
+                .and_then(|location| location.to_str().ok())
+                .and_then(|location| resp.url().join(location).ok());
+            let next = match next {
+                Some(next) => next,
+                None => return Ok(resp),
+            };
+
+            if next.origin() != resp.url().origin() {
+                log::debug!("Not sending credentials to {}", next);
+                credentials = None;
+            }
+
+            url = next.into_string();
         }
+
+        Err(format!("Too many redirects for {}", url))
     }
 
     /// Subscribe to feed and fetch items.
-    pub async fn subscribe(&self, url: &str) -> Result<Subscription, &'static str> {
-        let feed = self.fetch(&url).await?;
+    ///
+    /// Known web pages are first rewritten to their feed URL.
+    /// If `url` is a web page, the feeds it advertises are used instead.
+    ///
+    /// Credentials are only sent to the host of `url`.
+    pub async fn subscribe(
+        &self,
+        url: &str,
+        options: FetchOptions,
+    ) -> Result<Subscribed, &'static str> {
+        let resolved = match resolvers::resolve(url) {
+            Some(feed_url) => self.fetch_resolved(feed_url, url, &options).await,
+            None => None,
+        };
 
-        let new_subscription = NewSubscription::try_from(&url, &feed)?;
+        let (feed_url, feed, candidates) = match resolved {
+            Some((feed_url, feed)) => (feed_url, feed, Vec::new()),
+            None => self.find_feed(url, &options).await?,
+        };
+
+        // The feed can be on another host than the submitted page
+        let options = Self::options_for_host(&feed_url, url, options);
+
+        let mut new_subscription = NewSubscription::try_from(&feed_url, &feed.feed)?;
+        new_subscription.proxy = options.proxy;
+        new_subscription.credentials = match &options.credentials {
+            Some(credentials) if !credentials.is_empty() => {
+                Some(self.encrypt_credentials(credentials)?)
+            }
+            _ => None,
+        };
 
         let subscription = self
             .db
@@ -32,9 +355,128 @@ impl FeedManager {
             .await
             .unwrap();
 
-        self.store_new_entries(&subscription, feed.entries).await?;
+        self.events.send(Event::SubscriptionAdded(subscription.id));
+
+        let icons = Self::feed_icons(&feed.feed, &subscription);
+        let count = self.store_new_entries(&subscription, feed).await?;
+        self.send_new_items(&subscription, count);
 
-        Ok(subscription)
+        self.refresh_favicon(&subscription, icons).await;
+
+        Ok(Subscribed {
+            subscription,
+            candidates,
+        })
+    }
+
+    /// Fetch the feed a resolver found for `url`.
+    ///
+    /// Resolvers can be wrong, so failures are only logged.
+    async fn fetch_resolved(
+        &self,
+        feed_url: String,
+        url: &str,
+        options: &FetchOptions,
+    ) -> Option<(String, ParsedFeed)> {
+        let options = Self::options_for_host(&feed_url, url, options.clone());
+
+        match self.fetch(&feed_url, &options).await {
+            Ok(feed) => Some((feed_url, feed)),
+            Err(e) => {
+                log::warn!("Resolved feed {} of {} failed: {}", feed_url, url, e);
+                None
+            }
+        }
+    }
+
+    /// Find the feed at `url`, or the ones of the web page at `url`.
+    ///
+    /// Result is the feed's URL, the feed, and the candidates found on the
+    /// web page with the feed's first.
+    async fn find_feed(
+        &self,
+        url: &str,
+        options: &FetchOptions,
+    ) -> Result<(String, ParsedFeed, Vec<FeedLink>), &'static str> {
+        let download = self.download(url, options).await?;
+
+        let error = match Self::parse(url, &download.body) {
+            Ok(feed) => return Ok((url.to_owned(), feed, Vec::new())),
+            Err(e) => e,
+        };
+        if !download.is_html() {
+            return Err(error);
+        }
+
+        log::debug!("{} is a web page, looking for feeds", url);
+
+        let page = String::from_utf8_lossy(&download.body);
+        if let Some(feed_url) = resolvers::resolve_page(url, &page) {
+            if let Some((feed_url, feed)) = self.fetch_resolved(feed_url, url, options).await {
+                return Ok((feed_url, feed, Vec::new()));
+            }
+        }
+
+        let mut candidates = self.discover(url, &download.body, options).await;
+
+        let mut found = None;
+        for candidate in &candidates {
+            let candidate_options = Self::options_for_host(&candidate.url, url, options.clone());
+            if let Ok(feed) = self.fetch(&candidate.url, &candidate_options).await {
+                found = Some((candidate.url.clone(), feed));
+                break;
+            }
+        }
+
+        let (feed_url, feed) = found.ok_or("No feed found on this web page.")?;
+
+        // Put the subscribed feed first
+        if let Some(pos) = candidates.iter().position(|c| c.url == feed_url) {
+            let subscribed = candidates.remove(pos);
+            candidates.insert(0, subscribed);
+        }
+
+        Ok((feed_url, feed, candidates))
+    }
+
+    fn send_new_items(&self, subscription: &Subscription, count: usize) {
+        if count > 0 {
+            self.events.send(Event::NewItems {
+                subscription_id: subscription.id,
+                count,
+            });
+        }
+    }
+
+    /// Find the feeds of the web page at `url`.
+    ///
+    /// The page's alternate links are used if there are any, otherwise
+    /// common feed locations are tried, with credentials only if they are
+    /// on the page's host.
+    async fn discover(&self, url: &str, page: &[u8], options: &FetchOptions) -> Vec<FeedLink> {
+        let links = html::find_feed_links(&String::from_utf8_lossy(page), url);
+        if !links.is_empty() {
+            return links;
+        }
+
+        let mut found = Vec::new();
+        for path in html::COMMON_FEED_PATHS {
+            let candidate = match make_url_absolute(path, url) {
+                Ok(candidate) => candidate,
+                Err(_) => continue,
+            };
+
+            let options = Self::options_for_host(&candidate, url, options.clone());
+            if let Ok(feed) = self.fetch(&candidate, &options).await {
+                found.push(FeedLink {
+                    url: candidate,
+                    title: feed.feed.title.map(|t| t.content),
+                    media_type: None,
+                });
+            }
+        }
+
+        found
     }
 
     /// Fetch feed and store new items.
@@ -44,10 +486,23 @@ impl FeedManager {
     ///
     /// Result is the number of new items.
     pub async fn refresh(&self, subscription: &mut Subscription) -> Result<usize, &'static str> {
-        let result = self
-            .fetch(&subscription.feed_url)
-            .and_then(|feed| self.store_new_entries(&subscription, feed.entries))
-            .await;
+        let result = async {
+            let options = self.fetch_options(subscription)?;
+            let feed = self.fetch(&subscription.feed_url, &options).await?;
+
+            let icons = Self::feed_icons(&feed.feed, subscription);
+            let count = self.store_new_entries(&subscription, feed).await?;
+            self.send_new_items(subscription, count);
+
+            self.refresh_favicon(subscription, icons).await;
+
+            if subscription.fetch_full_content {
+                self.fetch_missing_full_contents(&subscription).await;
+            }
+
+            Ok(count)
+        }
+        .await;
 
         subscription.error_count = if result.is_ok() {
             0
@@ -69,41 +524,452 @@ impl FeedManager {
         result
     }
 
-    async fn fetch(&self, url: &str) -> Result<Feed, &'static str> {
-        let feed_bytes = self
-            .http_client
-            .get(url)
-            .send()
-            .and_then(|resp| resp.bytes())
+    /// Extract the article of `item` from its page, and store it.
+    ///
+    /// Failures are stored as an empty article so they aren't retried
+    /// on each refresh.
+    pub async fn fetch_full_content(
+        &self,
+        item: &Item,
+        subscription: &Subscription,
+    ) -> Result<String, &'static str> {
+        let result = async {
+            let options = self.linked_fetch_options(&item.url, subscription)?;
+            let download = self.download(&item.url, &options).await?;
+
+            let page = String::from_utf8_lossy(&download.body);
+            let article = html::extract_article(&page, subscription.content_selector.as_deref())
+                .ok_or("Could not find the article.")?;
+
+            Ok(self.clean_content(&article, &item.url, None, subscription))
+        }
+        .await;
+
+        self.db
+            .clone()
+            .set_item_full_content(item.id, result.clone().unwrap_or_default())
             .await
             .map_err(|e| {
-                log::error!("{}", e);
-                "Could not fetch feed."
+                log::error!("Could not store full content: {}", e);
+                "Database error."
             })?;
 
-        feed_rs::parser::parse(feed_bytes.as_ref()).map_err(|e| {
+        result
+    }
+
+    /// Fetch the full content of the newest items missing it.
+    async fn fetch_missing_full_contents(&self, subscription: &Subscription) {
+        let items = self
+            .db
+            .clone()
+            .find_items_without_full_content(subscription.id, MAX_FULL_CONTENTS_PER_REFRESH)
+            .await;
+
+        let items = match items {
+            Ok(items) => items,
+            Err(e) => {
+                log::error!("Could not get items: {}", e);
+                return;
+            }
+        };
+
+        for item in items {
+            if let Err(e) = self.fetch_full_content(&item, subscription).await {
+                log::warn!("No full content for {}: {}", item.url, e);
+            }
+        }
+    }
+
+    /// Get the categories of an entry, used as the tags of its item.
+    fn entry_tags(entry: &Entry) -> Vec<String> {
+        let mut tags: Vec<String> = Vec::new();
+
+        for category in &entry.categories {
+            let tag = category.term.trim();
+            if !tag.is_empty() && !tags.iter().any(|other| other.eq_ignore_ascii_case(tag)) {
+                tags.push(tag.to_owned());
+            }
+        }
+
+        tags
+    }
+
+    /// Get the icons a feed advertises, best first.
+    fn feed_icons(feed: &Feed, subscription: &Subscription) -> Vec<String> {
+        feed.icon
+            .iter()
+            .chain(&feed.logo)
+            .filter_map(|image| make_url_absolute(&image.uri, &subscription.feed_url).ok())
+            .collect()
+    }
+
+    /// Look for the subscription's favicon in the background, if it wasn't
+    /// in the last `FAVICON_REFRESH_DAYS` and isn't being looked for.
+    async fn refresh_favicon(&self, subscription: &Subscription, icons: Vec<String>) {
+        let now = chrono::Utc::now().naive_utc();
+        match self.db.clone().get_favicon(subscription.id).await {
+            Ok(Some(favicon))
+                if now - favicon.fetched_at < chrono::Duration::days(FAVICON_REFRESH_DAYS) =>
+            {
+                return
+            }
+            Ok(_) => (),
+            Err(e) => {
+                log::error!("Could not get favicon: {}", e);
+                return;
+            }
+        }
+
+        if !self
+            .pending_favicons
+            .lock()
+            .unwrap()
+            .insert(subscription.id)
+        {
+            return;
+        }
+
+        let feed_manager = self.clone();
+        let subscription = subscription.clone();
+        actix_web::rt::spawn(async move {
+            feed_manager.find_favicon(&subscription, icons).await;
+            feed_manager
+                .pending_favicons
+                .lock()
+                .unwrap()
+                .remove(&subscription.id);
+        });
+    }
+
+    /// Look for the subscription's favicon, and store it.
+    ///
+    /// The feed's `icons` are tried first, then the ones of its site.
+    async fn find_favicon(&self, subscription: &Subscription, icons: Vec<String>) {
+        let now = chrono::Utc::now().naive_utc();
+        let mut candidates = icons;
+
+        if let Some(site_url) = &subscription.site_url {
+            let page = match self.linked_fetch_options(site_url, subscription) {
+                Ok(options) => self.download(site_url, &options).await.ok(),
+                Err(_) => None,
+            };
+
+            if let Some(page) = page.filter(Download::is_html) {
+                let page_body = String::from_utf8_lossy(&page.body);
+                candidates.extend(html::find_icon_links(&page_body, site_url));
+            }
+        }
+
+        let site_url = subscription
+            .site_url
+            .as_deref()
+            .unwrap_or(&subscription.feed_url);
+        if let Ok(url) = make_url_absolute("/favicon.ico", site_url) {
+            candidates.push(url);
+        }
+
+        let mut favicon = Favicon {
+            subscription_id: subscription.id,
+            url: None,
+            content_type: None,
+            data: None,
+            fetched_at: now,
+        };
+
+        for url in candidates {
+            match self.fetch_favicon(&url, subscription).await {
+                Ok(download) => {
+                    log::debug!("Found favicon of {}: {}", subscription, url);
+                    favicon.url = Some(url);
+                    favicon.content_type = download.content_type;
+                    favicon.data = Some(download.body);
+                    break;
+                }
+                Err(e) => log::debug!("No favicon at {}: {}", url, e),
+            }
+        }
+
+        if let Err(e) = self.db.clone().set_favicon(favicon).await {
+            log::error!("Could not store favicon: {}", e);
+        }
+    }
+
+    async fn fetch_favicon(
+        &self,
+        url: &str,
+        subscription: &Subscription,
+    ) -> Result<Download, &'static str> {
+        let options = self.linked_fetch_options(url, subscription)?;
+        let download = self.download(url, &options).await?;
+
+        let is_image = download
+            .content_type
+            .as_deref()
+            .map(|content_type| content_type.trim().to_lowercase())
+            .map_or(false, |content_type| {
+                // SVG images can contain scripts
+                content_type.starts_with("image/") && !content_type.starts_with("image/svg")
+            });
+
+        if !is_image {
+            return Err("Not an allowed image.");
+        }
+
+        if download.body.is_empty() || download.body.len() > FAVICON_MAX_SIZE {
+            return Err("Invalid favicon size.");
+        }
+
+        Ok(download)
+    }
+
+    /// Download an enclosure of `subscription` to `path`.
+    ///
+    /// The result is the size of the file, which can't exceed `max_size`.
+    pub async fn download_enclosure(
+        &self,
+        enclosure: &Enclosure,
+        subscription: &Subscription,
+        path: &Path,
+        max_size: u64,
+    ) -> Result<u64, &'static str> {
+        let fetch_error = |e: String| {
+            log::error!("{}", e);
+            "Could not fetch enclosure."
+        };
+        let write_error = |e: web::BlockingError<io::Error>| {
+            log::error!("Could not write {}: {}", path.display(), e);
+            "Could not write enclosure."
+        };
+
+        let url = &enclosure.url;
+        let options = self.linked_fetch_options(url, subscription)?;
+
+        let mut resp = self
+            .send(url, &options, Some(self.cfg.download_timeout))
+            .await
+            .and_then(|resp| resp.error_for_status().map_err(|e| e.to_string()))
+            .map_err(fetch_error)?;
+
+        if matches!(resp.content_length(), Some(len) if len > max_size) {
+            log::error!("{} is too big: {:?} bytes", url, resp.content_length());
+            return Err("Enclosure is too big.");
+        }
+
+        let file_path = path.to_owned();
+        let mut file = web::block(move || std::fs::File::create(file_path))
+            .await
+            .map_err(write_error)?;
+
+        // Chunks are small, so they are written in batches
+        let mut size = 0;
+        let mut buffer = Vec::new();
+        let read_timeout = self.cfg.fetch_read_timeout;
+        while let Some(chunk) = Self::read_chunk(&mut resp, read_timeout)
+            .await
+            .map_err(fetch_error)?
+        {
+            size += chunk.len() as u64;
+            if size > max_size {
+                log::error!("{} is too big: over {} bytes", url, max_size);
+                return Err("Enclosure is too big.");
+            }
+
+            buffer.extend_from_slice(&chunk);
+            if buffer.len() >= WRITE_BUFFER_SIZE {
+                let data = std::mem::take(&mut buffer);
+                file = web::block(move || file.write_all(&data).map(|()| file))
+                    .await
+                    .map_err(write_error)?;
+            }
+        }
+
+        web::block(move || {
+            file.write_all(&buffer)?;
+            file.sync_all()
+        })
+        .await
+        .map_err(write_error)?;
+
+        Ok(size)
+    }
+
+    async fn fetch(&self, url: &str, options: &FetchOptions) -> Result<ParsedFeed, &'static str> {
+        let download = self.download(url, options).await?;
+
+        Self::parse(url, &download.body)
+    }
+
+    fn parse(url: &str, body: &[u8]) -> Result<ParsedFeed, &'static str> {
+        let feed = feed_rs::parser::parse(body).map_err(|e| {
             log::error!("Parse error for {}: {}", url, e);
             "Could not parse content as a feed."
+        })?;
+
+        Ok(ParsedFeed {
+            feed,
+            content_bases: xml_base::content_bases(body, url),
         })
     }
 
+    /// Download the body of `url`.
+    ///
+    /// The body is read chunk by chunk so responses bigger than
+    /// `fetch_max_size` are aborted early.
+    async fn download(&self, url: &str, options: &FetchOptions) -> Result<Download, &'static str> {
+        let fetch_error = |e: String| {
+            log::error!("{}", e);
+            "Could not fetch feed."
+        };
+
+        let mut resp = self.send(url, options, None).await.map_err(fetch_error)?;
+
+        let max_size = self.cfg.fetch_max_size;
+
+        let content_type = resp
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|val| val.to_str().ok())
+            .map(str::to_owned);
+
+        if matches!(resp.content_length(), Some(len) if len > max_size as u64) {
+            log::error!("{} is too big: {:?} bytes", url, resp.content_length());
+            return Err("Feed is too big.");
+        }
+
+        let read_timeout = self.cfg.fetch_read_timeout;
+        let mut body = Vec::new();
+        while let Some(chunk) = Self::read_chunk(&mut resp, read_timeout)
+            .await
+            .map_err(fetch_error)?
+        {
+            if body.len() + chunk.len() > max_size {
+                log::error!("{} is too big: over {} bytes", url, max_size);
+                return Err("Feed is too big.");
+            }
+
+            body.extend_from_slice(&chunk);
+        }
+
+        Ok(Download { body, content_type })
+    }
+
+    /// Make the raw content of an item safe to serve to clients.
+    ///
+    /// Relative URLs are resolved against the content's `xml:base`, or the
+    /// item's URL, or the feed's.
+    fn clean_content(
+        &self,
+        raw_content: &str,
+        url: &str,
+        content_base: Option<&str>,
+        subscription: &Subscription,
+    ) -> String {
+        let base_url = match content_base {
+            Some(content_base) => content_base,
+            None if reqwest::Url::parse(url).is_ok() => url,
+            None => &subscription.feed_url,
+        };
+
+        self.sanitizer
+            .clean(&html::absolutize_urls(raw_content, base_url))
+    }
+
+    /// Process the raw content of all items again, after the policy changed.
+    ///
+    /// Result is the number of items whose content changed.
+    pub async fn reprocess_items(&self) -> Result<usize, db::Error> {
+        let mut db = self.db.clone();
+        let mut count = 0;
+
+        for subscription in db.get_subscriptions().await? {
+            let contents: Vec<_> = db
+                .get_subscription_items(subscription.id)
+                .await?
+                .into_iter()
+                .filter_map(|item| self.reprocessed_content(&item, &subscription))
+                .collect();
+
+            count += contents.len();
+            db.update_items_content(contents).await?;
+        }
+
+        Ok(count)
+    }
+
+    /// Process the raw content of items without content, which were stored
+    /// before contents were sanitized.
+    ///
+    /// Result is the number of items whose content changed.
+    pub async fn reprocess_pending_items(&self) -> Result<usize, db::Error> {
+        let mut db = self.db.clone();
+        let mut count = 0;
+
+        let ids = db.get_items_to_reprocess().await?;
+        if !ids.is_empty() {
+            log::info!("Processing the content of {} items", ids.len());
+        }
+
+        for ids in ids.chunks(1000) {
+            let contents: Vec<_> = db
+                .get_items_and_subscriptions(ids.to_vec())
+                .await?
+                .into_iter()
+                .filter_map(|(item, subscription)| self.reprocessed_content(&item, &subscription))
+                .collect();
+
+            count += contents.len();
+            db.update_items_content(contents).await?;
+        }
+
+        Ok(count)
+    }
+
+    /// Get the new content of `item`, if it changed.
+    fn reprocessed_content(
+        &self,
+        item: &Item,
+        subscription: &Subscription,
+    ) -> Option<(db::Id, String)> {
+        let content = self.clean_content(
+            &item.raw_content,
+            &item.url,
+            item.content_base.as_deref(),
+            subscription,
+        );
+
+        if content != item.content {
+            Some((item.id, content))
+        } else {
+            None
+        }
+    }
+
+    /// Store new entries and update the ones whose content changed.
+    ///
+    /// Result is the number of new items.
     async fn store_new_entries(
         &self,
         subscription: &Subscription,
-        entries: Vec<Entry>,
+        feed: ParsedFeed,
     ) -> Result<usize, &'static str> {
+        let db_error = |e: db::Error| {
+            log::error!("Could not store items: {}", e);
+            "Database error."
+        };
+
         let mut db = self.db.clone();
 
-        let existing = db.get_subscription_items(subscription.id).await.unwrap();
-        let existing = existing
-            .iter()
-            .map(|item| (item.title.as_str(), item.url.as_str()))
-            .collect::<std::collections::HashSet<_>>();
+        let entries = feed.feed.entries;
+        // Bases can't be matched to entries feed_rs doesn't see the same way
+        let mut content_bases = feed.content_bases;
+        if content_bases.len() != entries.len() {
+            content_bases = vec![None; entries.len()];
+        }
 
-        let mut any_ok = false;
-        let mut count = 0;
-        for entry in &entries {
-            let new_item = match NewItem::try_from(entry, &subscription) {
+        let mut seen = HashSet::new();
+        let mut entry_items = Vec::with_capacity(entries.len());
+        for (entry, content_base) in entries.iter().zip(content_bases) {
+            let mut new_item = match NewItem::try_from(entry, &subscription) {
                 Ok(item) => item,
                 Err(e) => {
                     log::error!("{}", e);
@@ -111,25 +977,161 @@ impl FeedManager {
                     continue;
                 }
             };
+            new_item.content_base = content_base;
 
-            any_ok = true;
+            if seen.insert(new_item.guid.clone()) {
+                let extras = NewItemExtras {
+                    labels: Vec::new(),
+                    tags: Self::entry_tags(entry),
+                    enclosures: NewEnclosure::from_entry(entry, &subscription),
+                };
+                // Entries without a date get the current one, which isn't an update
+                let updated = entry.updated.map(|updated| updated.naive_utc());
+                entry_items.push((new_item, extras, updated));
+            } else {
+                log::trace!("Ignoring duplicate entry: {}", new_item.guid);
+            }
+        }
+
+        if entry_items.is_empty() && !entries.is_empty() {
+            return Err("No entry could be parsed.");
+        }
+
+        // Purged items stay purged while their entry is in the feed
+        let guids: Vec<String> = entry_items
+            .iter()
+            .map(|(item, _, _)| item.guid.clone())
+            .collect();
+        let purged = db
+            .find_purged_items(subscription.id, guids.clone())
+            .await
+            .map_err(db_error)?;
+        if !purged.is_empty() {
+            let purged: HashSet<_> = purged.into_iter().collect();
+            entry_items.retain(|(item, _, _)| !purged.contains(&item.guid));
+        }
+        db.forget_purged_items(subscription.id, guids)
+            .await
+            .map_err(db_error)?;
+
+        // Items stored before GUIDs were used have their URL as GUID
+        let keys = entry_items
+            .iter()
+            .flat_map(|(item, _, _)| vec![item.guid.clone(), item.url.clone()])
+            .collect();
+
+        let existing = db
+            .find_item_versions(subscription.id, keys)
+            .await
+            .map_err(db_error)?;
+        let existing = existing
+            .iter()
+            .map(|version| (version.guid.as_str(), version))
+            .collect::<HashMap<_, _>>();
+
+        // An item can only be matched by one entry, and matching GUIDs
+        // have priority over URLs
+        let mut claimed = entry_items
+            .iter()
+            .filter_map(|(item, _, _)| existing.get(item.guid.as_str()))
+            .map(|version| version.id)
+            .collect::<HashSet<_>>();
+
+        let rules = RuleSet::new(db.get_rules().await.map_err(db_error)?);
+        let categories = if rules.is_empty() {
+            Vec::new()
+        } else {
+            db.get_subscription_categories(subscription.id)
+                .await
+                .map_err(db_error)?
+        };
+
+        let mut new_items = Vec::new();
+        let mut extras = HashMap::new();
+        let mut updates = Vec::new();
+        let mut mark_unread = Vec::new();
 
-            if existing.contains(&(new_item.title.as_str(), new_item.url.as_str())) {
+        for (mut new_item, mut item_extras, entry_updated) in entry_items {
+            let version = match existing.get(new_item.guid.as_str()) {
+                Some(version) => Some(version),
+                None => existing
+                    .get(new_item.url.as_str())
+                    .filter(|version| claimed.insert(version.id)),
+            };
+
+            let version = match version {
+                Some(version) => version,
+                None => {
+                    new_item.content = self.clean_content(
+                        &new_item.raw_content,
+                        &new_item.url,
+                        new_item.content_base.as_deref(),
+                        subscription,
+                    );
+
+                    let target = Target {
+                        title: &new_item.title,
+                        content: &new_item.content,
+                        author: new_item.author.as_deref(),
+                        url: &new_item.url,
+                        categories: &item_extras.tags,
+                    };
+                    let outcome = rules.apply(subscription.id, &categories, &target);
+
+                    if outcome.drop {
+                        log::debug!("Dropping item: {}", new_item.title);
+                        continue;
+                    }
+
+                    let now = chrono::Utc::now().naive_utc();
+                    if outcome.read && !new_item.is_read {
+                        new_item.is_read = true;
+                        new_item.read_at = Some(now);
+                    }
+                    if outcome.star && !new_item.is_starred {
+                        new_item.is_starred = true;
+                        new_item.starred_at = Some(now);
+                    }
+                    item_extras.labels = outcome.labels;
+
+                    let has_extras = !item_extras.labels.is_empty()
+                        || !item_extras.tags.is_empty()
+                        || !item_extras.enclosures.is_empty();
+                    if has_extras {
+                        extras.insert(new_item.guid.clone(), item_extras);
+                    }
+
+                    new_items.push(new_item);
+                    continue;
+                }
+            };
+
+            if matches!(entry_updated, Some(updated) if updated > version.updated) {
+                log::debug!("Updating item: {}", new_item.title);
+
+                if self.cfg.mark_updated_unread {
+                    mark_unread.push(version.id);
+                }
+            } else if new_item.guid == version.guid {
                 log::trace!("Ignoring existing item: {}", new_item.title);
                 continue;
             }
 
-            count += 1;
-            db.create_item(new_item).await.map_err(|e| {
-                log::error!("Could not store item: {}", e);
-                "Database error."
-            })?;
+            new_item.content = self.clean_content(
+                &new_item.raw_content,
+                &new_item.url,
+                new_item.content_base.as_deref(),
+                subscription,
+            );
+            updates.push((ItemUpdate::new(version.id, new_item), item_extras));
         }
 
-        if any_ok || entries.is_empty() {
-            Ok(count)
-        } else {
-            Err("No entry could be parsed.")
-        }
+        let count = new_items.len();
+
+        db.store_items(new_items, extras, updates, mark_unread)
+            .await
+            .map_err(db_error)?;
+
+        Ok(count)
     }
 }
@@ -0,0 +1,195 @@
+use kuchiki::traits::TendrilSink;
+use kuchiki::NodeRef;
+
+use crate::utils::make_url_absolute;
+
+/// Attributes containing a single URL.
+const URL_ATTRIBUTES: &[&str] = &["href", "src", "poster"];
+
+/// Make the URLs in `html` absolute, so clients don't resolve them against
+/// their own host.
+///
+/// URLs are resolved against `base_url`, unless the content has a `<base>`
+/// or `xml:base` attributes, which are honored.
+pub fn absolutize_urls(html: &str, base_url: &str) -> String {
+    let document = kuchiki::parse_html().one(html);
+
+    let base_url = document
+        .select_first("base[href]")
+        .ok()
+        .and_then(|base| {
+            let href = base.attributes.borrow().get("href")?.to_owned();
+            make_url_absolute(&href, base_url).ok()
+        })
+        .unwrap_or_else(|| base_url.to_owned());
+
+    let elements = match document.select("[href], [src], [srcset], [poster]") {
+        Ok(elements) => elements,
+        Err(()) => return html.to_owned(),
+    };
+
+    let mut changed = false;
+
+    for element in elements {
+        let base_url = element_base_url(element.as_node(), &base_url);
+        let mut attributes = element.attributes.borrow_mut();
+
+        for name in URL_ATTRIBUTES {
+            let absolute = match attributes.get(*name) {
+                Some(url) => absolutize(url, &base_url),
+                None => continue,
+            };
+
+            if let Some(absolute) = absolute {
+                attributes.insert(*name, absolute);
+                changed = true;
+            }
+        }
+
+        let srcset = attributes
+            .get("srcset")
+            .and_then(|srcset| map_srcset(srcset, |url| absolutize(url, &base_url)));
+        if let Some(srcset) = srcset {
+            attributes.insert("srcset", srcset);
+            changed = true;
+        }
+    }
+
+    if !changed {
+        return html.to_owned();
+    }
+
+    super::serialize_body(&document).unwrap_or_else(|| html.to_owned())
+}
+
+/// Get the base URL of `node`, from the `xml:base` of it and its ancestors.
+fn element_base_url(node: &NodeRef, base_url: &str) -> String {
+    let bases: Vec<String> = node
+        .inclusive_ancestors()
+        .filter_map(|ancestor| {
+            let element = ancestor.as_element()?;
+            let attributes = element.attributes.borrow();
+            attributes.get("xml:base").map(str::to_owned)
+        })
+        .collect();
+
+    // Outermost first, each one is relative to the previous
+    bases
+        .iter()
+        .rev()
+        .fold(base_url.to_owned(), |base, xml_base| {
+            make_url_absolute(xml_base.trim(), &base).unwrap_or(base)
+        })
+}
+
+/// Get the absolute version of `url`, if it's relative.
+fn absolutize(url: &str, base_url: &str) -> Option<String> {
+    let url = url.trim();
+
+    // Fragments point inside the content, and others already have a scheme
+    if url.is_empty() || url.starts_with('#') || has_scheme(url) {
+        return None;
+    }
+
+    make_url_absolute(url, base_url).ok()
+}
+
+/// Replace the URLs of a `srcset` using `f`.
+///
+/// Returns `None` if no URL was replaced.
+pub(super) fn map_srcset<F>(srcset: &str, f: F) -> Option<String>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let mut changed = false;
+
+    let candidates: Vec<String> = srcset
+        .split(',')
+        .map(|candidate| {
+            let candidate = candidate.trim();
+            let (url, descriptor) = match candidate.find(char::is_whitespace) {
+                Some(i) => candidate.split_at(i),
+                None => (candidate, ""),
+            };
+
+            match f(url) {
+                Some(url) => {
+                    changed = true;
+                    format!("{}{}", url, descriptor)
+                }
+                None => candidate.to_owned(),
+            }
+        })
+        .collect();
+
+    if changed {
+        Some(candidates.join(", "))
+    } else {
+        None
+    }
+}
+
+fn has_scheme(url: &str) -> bool {
+    match url.find(':') {
+        Some(i) => {
+            let scheme = &url[..i];
+            !scheme.is_empty()
+                && scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+                && scheme
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+        }
+        None => false,
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE: &str = "https://example.com/blog/post.html";
+
+    #[test]
+    fn absolutize_attributes() {
+        let html = concat!(
+            r##"<p><a href="../about">About</a> <a href="#top">Top</a> "##,
+            r#"<a href="mailto:me@example.com">Mail</a>"#,
+            r#"<img src="/img/a.png" srcset="a.png 1x, //cdn.example.com/a@2x.png 2x">"#,
+            r#"<video poster="poster.jpg" src="https://media.example.com/v.mp4"></video></p>"#,
+        );
+
+        assert_eq!(
+            absolutize_urls(html, BASE),
+            concat!(
+                r##"<p><a href="https://example.com/about">About</a> <a href="#top">Top</a> "##,
+                r#"<a href="mailto:me@example.com">Mail</a>"#,
+                r#"<img src="https://example.com/img/a.png" "#,
+                r#"srcset="https://example.com/blog/a.png 1x, https://cdn.example.com/a@2x.png 2x">"#,
+                r#"<video poster="https://example.com/blog/poster.jpg" "#,
+                r#"src="https://media.example.com/v.mp4"></video></p>"#,
+            )
+        );
+    }
+
+    #[test]
+    fn honor_bases() {
+        let html = concat!(
+            r#"<div xml:base="/docs/"><p xml:base="guide/">"#,
+            r#"<a href="intro">Intro</a></p></div>"#,
+        );
+        let expected = r#"href="https://example.com/docs/guide/intro""#;
+        assert!(absolutize_urls(html, BASE).contains(expected));
+
+        let html = r#"<base href="https://other.example.com/"><img src="a.png">"#;
+        let expected = r#"src="https://other.example.com/a.png""#;
+        assert!(absolutize_urls(html, BASE).contains(expected));
+    }
+
+    #[test]
+    fn keep_unchanged_content() {
+        let html = r#"<p>Hello <a href="https://example.com/">world</a><br></p>"#;
+
+        assert_eq!(absolutize_urls(html, BASE), html);
+    }
+}
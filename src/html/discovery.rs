@@ -0,0 +1,247 @@
+use kuchiki::traits::TendrilSink;
+use serde::Serialize;
+
+use crate::utils::make_url_absolute;
+
+/// Feed types, in order of preference.
+const FEED_MEDIA_TYPES: &[&str] = &[
+    "application/atom+xml",
+    "application/rss+xml",
+    "application/feed+json",
+];
+
+/// Locations tried when a web page doesn't advertise any feed.
+pub const COMMON_FEED_PATHS: &[&str] =
+    &["/feed", "/rss.xml", "/atom.xml", "/feed.xml", "/index.xml"];
+
+/// Links to icons, `rel="shortcut icon"` included.
+const ICON_LINKS_SELECTOR: &str = "link[rel~=icon][href], link[rel~=apple-touch-icon][href]";
+
+/// A feed found on a web page.
+#[derive(Debug, Clone, Serialize)]
+pub struct FeedLink {
+    pub url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub media_type: Option<String>,
+}
+
+/// Find the feeds advertised by an HTML page using `<link rel="alternate">`.
+///
+/// Results are sorted by feed type preference, then by order of appearance.
+pub fn find_feed_links(html: &str, page_url: &str) -> Vec<FeedLink> {
+    let document = kuchiki::parse_html().one(html);
+    let base_url = base_url(&document, page_url);
+
+    let links = match document.select("link[rel~=alternate][href][type]") {
+        Ok(links) => links,
+        Err(()) => return Vec::new(),
+    };
+
+    let mut feeds: Vec<(usize, FeedLink)> = Vec::new();
+    for link in links {
+        let attributes = link.attributes.borrow();
+
+        let media_type = attributes.get("type").unwrap_or("").trim().to_lowercase();
+        let rank = match FEED_MEDIA_TYPES.iter().position(|t| *t == media_type) {
+            Some(rank) => rank,
+            None => continue,
+        };
+
+        let url = match make_url_absolute(attributes.get("href").unwrap_or(""), &base_url) {
+            Ok(url) => url,
+            Err(_) => continue,
+        };
+
+        if feeds.iter().any(|(_, feed)| feed.url == url) {
+            continue;
+        }
+
+        feeds.push((
+            rank,
+            FeedLink {
+                url,
+                title: attributes.get("title").map(str::to_owned),
+                media_type: Some(media_type),
+            },
+        ));
+    }
+
+    // Stable sort to keep the page's order for a given type
+    feeds.sort_by_key(|(rank, _)| *rank);
+
+    feeds.into_iter().map(|(_, feed)| feed).collect()
+}
+
+/// Find the icons of an HTML page using `<link rel="icon">`, in order of
+/// appearance.
+pub fn find_icon_links(html: &str, page_url: &str) -> Vec<String> {
+    let document = kuchiki::parse_html().one(html);
+    let base_url = base_url(&document, page_url);
+
+    let links = match document.select(ICON_LINKS_SELECTOR) {
+        Ok(links) => links,
+        Err(()) => return Vec::new(),
+    };
+
+    let mut icons: Vec<String> = Vec::new();
+    for link in links {
+        let attributes = link.attributes.borrow();
+
+        if let Ok(url) = make_url_absolute(attributes.get("href").unwrap_or(""), &base_url) {
+            if !icons.contains(&url) {
+                icons.push(url);
+            }
+        }
+    }
+
+    icons
+}
+
+/// Find the canonical URL of an HTML page, using `<link rel="canonical">`
+/// or the OpenGraph URL.
+pub fn find_canonical_url(html: &str, page_url: &str) -> Option<String> {
+    let document = kuchiki::parse_html().one(html);
+    let base_url = base_url(&document, page_url);
+
+    let href = document
+        .select_first("link[rel~=canonical][href]")
+        .ok()
+        .and_then(|link| link.attributes.borrow().get("href").map(str::to_owned))
+        .or_else(|| {
+            let meta = document
+                .select_first(r#"meta[property="og:url"][content]"#)
+                .ok()?;
+            let content = meta.attributes.borrow().get("content")?.to_owned();
+            Some(content)
+        })?;
+
+    make_url_absolute(href.trim(), &base_url).ok()
+}
+
+/// Get the URL relative URLs of a page resolve against, using `<base>` if
+/// present.
+fn base_url(document: &kuchiki::NodeRef, page_url: &str) -> String {
+    document
+        .select_first("base[href]")
+        .ok()
+        .and_then(|base| {
+            let href = base.attributes.borrow().get("href")?.to_owned();
+            make_url_absolute(&href, page_url).ok()
+        })
+        .unwrap_or_else(|| page_url.to_owned())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAGE_URL: &str = "https://example.com/blog/post.html";
+
+    fn urls(feeds: &[FeedLink]) -> Vec<&str> {
+        feeds.iter().map(|feed| feed.url.as_str()).collect()
+    }
+
+    #[test]
+    fn feed_links_by_type() {
+        let html = r#"<html><head>
+            <link rel="alternate" type="application/feed+json" href="/feed.json">
+            <link rel="alternate" type="application/rss+xml" href="rss.xml" title="RSS">
+            <link rel="alternate" type="text/html" href="/fr/">
+            <link rel="alternate" type="application/atom+xml" href="/atom.xml">
+            <link rel="alternate" type="application/atom+xml" href="/comments.xml">
+            <link rel="stylesheet" type="application/rss+xml" href="/not-a-feed.xml">
+        </head></html>"#;
+
+        let feeds = find_feed_links(html, PAGE_URL);
+
+        assert_eq!(
+            urls(&feeds),
+            vec![
+                "https://example.com/atom.xml",
+                "https://example.com/comments.xml",
+                "https://example.com/blog/rss.xml",
+                "https://example.com/feed.json",
+            ]
+        );
+        assert_eq!(feeds[2].title.as_deref(), Some("RSS"));
+        assert_eq!(feeds[2].media_type.as_deref(), Some("application/rss+xml"));
+    }
+
+    #[test]
+    fn feed_links_with_several_rels() {
+        let html = r#"<link rel="home alternate" type="Application/RSS+XML" href="/feed">
+            <link rel="alternate" type="application/rss+xml" href="https://example.com/feed">"#;
+
+        assert_eq!(
+            urls(&find_feed_links(html, PAGE_URL)),
+            vec!["https://example.com/feed"]
+        );
+    }
+
+    #[test]
+    fn feed_links_without_head() {
+        let html = r#"<body><p>Text</p>
+            <link rel="alternate" type="application/atom+xml" href="atom.xml"></body>"#;
+
+        assert_eq!(
+            urls(&find_feed_links(html, PAGE_URL)),
+            vec!["https://example.com/blog/atom.xml"]
+        );
+        assert!(find_feed_links("<p>No feed</p>", PAGE_URL).is_empty());
+    }
+
+    #[test]
+    fn feed_links_honor_base() {
+        let html = r#"<head><base href="https://cdn.example.org/site/">
+            <link rel="alternate" type="application/atom+xml" href="atom.xml"></head>"#;
+
+        assert_eq!(
+            urls(&find_feed_links(html, PAGE_URL)),
+            vec!["https://cdn.example.org/site/atom.xml"]
+        );
+    }
+
+    #[test]
+    fn icon_links() {
+        let html = r#"<head>
+            <link rel="shortcut icon" href="/favicon.ico">
+            <link rel="apple-touch-icon" href="touch.png">
+            <link rel="icon" href="https://example.com/favicon.ico">
+            <link rel="stylesheet" href="/style.css">
+            <link rel="icon">
+        </head>"#;
+
+        assert_eq!(
+            find_icon_links(html, PAGE_URL),
+            vec![
+                "https://example.com/favicon.ico",
+                "https://example.com/blog/touch.png",
+            ]
+        );
+        assert_eq!(
+            find_icon_links(r#"<link rel="icon" href="icon.png">"#, PAGE_URL),
+            vec!["https://example.com/blog/icon.png"]
+        );
+    }
+
+    #[test]
+    fn canonical_url() {
+        let html = r#"<head><link rel="canonical nofollow" href="/post">
+            <meta property="og:url" content="https://example.com/og"></head>"#;
+        assert_eq!(
+            find_canonical_url(html, PAGE_URL).as_deref(),
+            Some("https://example.com/post")
+        );
+
+        let html = r#"<meta property="og:url" content=" other.html ">"#;
+        assert_eq!(
+            find_canonical_url(html, PAGE_URL).as_deref(),
+            Some("https://example.com/blog/other.html")
+        );
+
+        assert_eq!(find_canonical_url("<p>Text</p>", PAGE_URL), None);
+    }
+}
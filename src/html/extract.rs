@@ -0,0 +1,136 @@
+use kuchiki::traits::TendrilSink;
+use kuchiki::NodeRef;
+use std::collections::HashMap;
+
+/// Elements that never contain the article.
+const NOISE_SELECTOR: &str = "script, style, noscript, template, nav, header, footer, aside, \
+                              form, button, iframe, [role=navigation], [role=complementary], \
+                              [aria-hidden=true]";
+
+/// Elements marked as being the article by the page.
+const ARTICLE_SELECTORS: &[&str] = &["[itemprop=articleBody]", "article", "[role=main]", "main"];
+
+/// Paragraphs shorter than this are ignored when scoring.
+const MIN_PARAGRAPH_LEN: usize = 25;
+
+/// Ensure `selector` is a valid CSS selector.
+pub fn check_selector(selector: &str) -> Result<(), &'static str> {
+    kuchiki::Selectors::compile(selector)
+        .map(|_| ())
+        .map_err(|()| "Invalid CSS selector.")
+}
+
+/// Extract the main article of a web page.
+///
+/// If `selector` is set, the content of all matching elements is used.
+/// Otherwise, the article is found readability-style: elements get scored
+/// by the amount of text in the paragraphs they contain.
+pub fn extract_article(html: &str, selector: Option<&str>) -> Option<String> {
+    let document = kuchiki::parse_html().one(html);
+
+    if let Some(selector) = selector {
+        let content: String = document
+            .select(selector)
+            .ok()?
+            .map(|element| element.as_node().to_string())
+            .collect();
+
+        return Some(content).filter(|content| !content.is_empty());
+    }
+
+    if let Ok(noise) = document.select(NOISE_SELECTOR) {
+        for node in noise
+            .map(|element| element.as_node().clone())
+            .collect::<Vec<_>>()
+        {
+            node.detach();
+        }
+    }
+
+    let article = ARTICLE_SELECTORS
+        .iter()
+        .filter_map(|selector| document.select_first(selector).ok())
+        .map(|element| element.as_node().clone())
+        .find(|node| text_len(node) >= MIN_PARAGRAPH_LEN)
+        .or_else(|| best_candidate(&document))?;
+
+    let content: String = article.children().map(|child| child.to_string()).collect();
+
+    Some(content).filter(|content| !content.trim().is_empty())
+}
+
+/// Find the element containing the most paragraph text.
+///
+/// Parents get each paragraph's length, grandparents half of it.
+fn best_candidate(document: &NodeRef) -> Option<NodeRef> {
+    let mut scores: HashMap<*const kuchiki::Node, (NodeRef, usize)> = HashMap::new();
+
+    for paragraph in document.select("p, pre, td").ok()? {
+        let len = text_len(paragraph.as_node());
+        if len < MIN_PARAGRAPH_LEN {
+            continue;
+        }
+
+        let ancestors = paragraph.as_node().ancestors().take(2);
+        for (depth, ancestor) in ancestors.enumerate() {
+            if ancestor.as_element().is_none() {
+                break;
+            }
+
+            let key = &*ancestor as *const kuchiki::Node;
+            let score = len >> depth;
+            scores.entry(key).or_insert_with(|| (ancestor.clone(), 0)).1 += score;
+        }
+    }
+
+    scores
+        .values()
+        .max_by_key(|(_, score)| *score)
+        .map(|(node, _)| node.clone())
+}
+
+fn text_len(node: &NodeRef) -> usize {
+    node.text_contents().trim().len()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAGE: &str = r#"
+        <html><body>
+            <nav><p>Home | About | A very long list of links to other pages</p></nav>
+            <div class="sidebar"><p>Short</p></div>
+            <div id="post">
+                <h1>Title</h1>
+                <p>The first paragraph of the article, long enough to count.</p>
+                <p>The second paragraph of the article, also long enough.</p>
+                <script>track()</script>
+            </div>
+            <footer><p>Copyright and a long footer text nobody reads at all</p></footer>
+        </body></html>
+    "#;
+
+    #[test]
+    fn extract_by_score() {
+        let content = extract_article(PAGE, None).unwrap();
+
+        assert!(content.contains("<h1>Title</h1>"));
+        assert!(content.contains("The second paragraph"));
+        for unwanted in &["Home", "Short", "track", "Copyright"] {
+            assert!(!content.contains(unwanted), "{} in {}", unwanted, content);
+        }
+    }
+
+    #[test]
+    fn extract_with_selector() {
+        assert_eq!(
+            extract_article(PAGE, Some("#post h1")).as_deref(),
+            Some("<h1>Title</h1>")
+        );
+        assert_eq!(extract_article(PAGE, Some(".missing")), None);
+        assert!(check_selector("#post > p").is_ok());
+        assert!(check_selector("#post >").is_err());
+    }
+}
@@ -0,0 +1,76 @@
+use kuchiki::traits::TendrilSink;
+
+use super::absolutize::map_srcset;
+
+/// Replace the URLs of images in `html` using `f`.
+///
+/// URLs for which `f` returns `None` are kept.
+pub fn rewrite_image_urls<F>(html: &str, f: F) -> String
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let document = kuchiki::parse_html().one(html);
+
+    let elements = match document.select("img, picture source, video[poster]") {
+        Ok(elements) => elements,
+        Err(()) => return html.to_owned(),
+    };
+
+    let mut changed = false;
+
+    for element in elements {
+        let mut attributes = element.attributes.borrow_mut();
+
+        for name in &["src", "poster"] {
+            // Only images, <video src> is left alone
+            if *name == "src" && &*element.name.local != "img" {
+                continue;
+            }
+
+            if let Some(url) = attributes.get(*name).and_then(|url| f(url)) {
+                attributes.insert(*name, url);
+                changed = true;
+            }
+        }
+
+        if let Some(srcset) = attributes.get("srcset").and_then(|s| map_srcset(s, &f)) {
+            attributes.insert("srcset", srcset);
+            changed = true;
+        }
+    }
+
+    if !changed {
+        return html.to_owned();
+    }
+
+    super::serialize_body(&document).unwrap_or_else(|| html.to_owned())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rewrite_images() {
+        let html = concat!(
+            r#"<p><a href="https://example.com/">link</a>"#,
+            r#"<img src="https://example.com/a.png" srcset="https://example.com/b.png 2x">"#,
+            r#"<video poster="https://example.com/p.jpg" src="https://example.com/v.mp4">"#,
+            r#"</video></p>"#,
+        );
+
+        let rewritten = rewrite_image_urls(html, |url| Some(format!("/proxy/{}", url)));
+
+        assert_eq!(
+            rewritten,
+            concat!(
+                r#"<p><a href="https://example.com/">link</a>"#,
+                r#"<img src="/proxy/https://example.com/a.png" "#,
+                r#"srcset="/proxy/https://example.com/b.png 2x">"#,
+                r#"<video poster="/proxy/https://example.com/p.jpg" "#,
+                r#"src="https://example.com/v.mp4"></video></p>"#,
+            )
+        );
+    }
+}
@@ -0,0 +1,41 @@
+mod absolutize;
+mod discovery;
+mod extract;
+mod images;
+mod sanitize;
+
+pub use absolutize::absolutize_urls;
+pub use discovery::{
+    find_canonical_url, find_feed_links, find_icon_links, FeedLink, COMMON_FEED_PATHS,
+};
+pub use extract::{check_selector, extract_article};
+pub use images::rewrite_image_urls;
+pub use sanitize::Sanitizer;
+
+
+/// Guess whether `body` is an HTML document.
+pub fn looks_like_html(body: &[u8]) -> bool {
+    let start = &body[..body.len().min(512)];
+    let start = String::from_utf8_lossy(start).trim_start().to_lowercase();
+
+    start.starts_with("<!doctype html") || start.starts_with("<html")
+}
+
+/// Get the text of an HTML fragment, without its markup.
+pub fn text_content(html: &str) -> String {
+    use kuchiki::traits::TendrilSink;
+
+    kuchiki::parse_html().one(html).text_contents()
+}
+
+/// Serialize the content of a parsed fragment, without the wrapping `<body>`.
+fn serialize_body(document: &kuchiki::NodeRef) -> Option<String> {
+    let body = document.select_first("body").ok()?;
+
+    Some(
+        body.as_node()
+            .children()
+            .map(|child| child.to_string())
+            .collect(),
+    )
+}
@@ -0,0 +1,137 @@
+use kuchiki::traits::TendrilSink;
+
+/// Cleans item content before it is served to clients.
+///
+/// Only an allowlist of tags and attributes is kept, so scripts, styles and
+/// event handlers are removed. Tracking pixels are removed too, as well as
+/// iframes, unless their source is on an allowed host.
+#[derive(Debug, Clone, Default)]
+pub struct Sanitizer {
+    iframe_hosts: Vec<String>,
+}
+
+impl Sanitizer {
+    pub fn new(iframe_hosts: &[String]) -> Self {
+        Self {
+            iframe_hosts: iframe_hosts
+                .iter()
+                .map(|host| host.to_lowercase())
+                .collect(),
+        }
+    }
+
+    pub fn clean(&self, html: &str) -> String {
+        let html = self.remove_unwanted(html);
+
+        let mut builder = ammonia::Builder::default();
+        builder.add_tag_attributes("img", &["width", "height", "srcset"]);
+        if !self.iframe_hosts.is_empty() {
+            builder
+                .add_tags(&["iframe"])
+                .add_tag_attributes("iframe", &["src", "width", "height", "allowfullscreen"]);
+        }
+
+        builder.clean(&html).to_string()
+    }
+
+    /// Remove the elements ammonia can't tell apart by tag and attribute name.
+    fn remove_unwanted(&self, html: &str) -> String {
+        let document = kuchiki::parse_html().one(html);
+
+        let mut unwanted = Vec::new();
+
+        if let Ok(images) = document.select("img") {
+            unwanted.extend(
+                images
+                    .filter(|img| is_tracking_pixel(&img.attributes.borrow()))
+                    .map(|img| img.as_node().clone()),
+            );
+        }
+
+        if let Ok(iframes) = document.select("iframe") {
+            unwanted.extend(
+                iframes
+                    .filter(|iframe| {
+                        let attributes = iframe.attributes.borrow();
+                        !self.is_allowed_iframe(attributes.get("src").unwrap_or(""))
+                    })
+                    .map(|iframe| iframe.as_node().clone()),
+            );
+        }
+
+        if unwanted.is_empty() {
+            return html.to_owned();
+        }
+
+        for node in unwanted {
+            node.detach();
+        }
+
+        super::serialize_body(&document).unwrap_or_default()
+    }
+
+    fn is_allowed_iframe(&self, src: &str) -> bool {
+        let host = match reqwest::Url::parse(src) {
+            Ok(url) if url.scheme() == "https" => url.host_str().map(str::to_lowercase),
+            _ => None,
+        };
+
+        match host {
+            Some(host) => self.iframe_hosts.iter().any(|allowed| *allowed == host),
+            None => false,
+        }
+    }
+}
+
+/// Images of at most 1x1 pixels are only used to track readers.
+fn is_tracking_pixel(attributes: &kuchiki::Attributes) -> bool {
+    let is_tiny = |name: &str| {
+        attributes
+            .get(name)
+            .and_then(|value| value.trim().trim_end_matches("px").parse::<u32>().ok())
+            .map_or(false, |value| value <= 1)
+    };
+
+    is_tiny("width") && is_tiny("height")
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn remove_unsafe_content() {
+        let sanitizer = Sanitizer::default();
+
+        let html = sanitizer.clean(concat!(
+            r#"<p onclick="steal()">Hello <script>alert(1)</script>"#,
+            r#"<a href="javascript:steal()">link</a>"#,
+            r#"<img src="https://example.com/a.png" width="640">"#,
+            r#"<img src="https://tracker.example.com/p.gif" width="1" height="1">"#,
+            r#"<iframe src="https://www.youtube.com/embed/x"></iframe></p>"#,
+        ));
+
+        assert!(html.starts_with("<p>Hello "));
+        assert!(html.contains(r#"<img src="https://example.com/a.png" width="640">"#));
+        for unwanted in &["steal", "alert", "tracker", "iframe"] {
+            assert!(!html.contains(unwanted), "{} in {}", unwanted, html);
+        }
+    }
+
+    #[test]
+    fn allow_iframes_from_hosts() {
+        let sanitizer = Sanitizer::new(&["www.YouTube.com".to_owned()]);
+
+        let html = sanitizer.clean(concat!(
+            r#"<iframe src="https://www.youtube.com/embed/x" onload="steal()"></iframe>"#,
+            r#"<iframe src="http://www.youtube.com/embed/y"></iframe>"#,
+            r#"<iframe src="https://evil.example.com/"></iframe>"#,
+        ));
+
+        assert_eq!(
+            html,
+            r#"<iframe src="https://www.youtube.com/embed/x"></iframe>"#
+        );
+    }
+}
@@ -0,0 +1,683 @@
+//! Proxy for images embedded in items, so they don't load from third parties.
+//!
+//! Images are loaded by `<img>` elements, which can't authenticate, so
+//! proxied URLs are signed with an HMAC instead and only URLs freader
+//! generated can be fetched. Images are only fetched from public addresses,
+//! to keep the server's networks out of reach.
+
+use actix_web::{dev, http::header, web, HttpResponse};
+use hmac::{Hmac, Mac, NewMac};
+use rand::Rng;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Read, Write};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use crate::feed_manager::{FeedManager, MAX_REDIRECTS};
+use crate::prelude::*;
+
+type HmacSha256 = Hmac<Sha256>;
+
+const PROXY_PATH: &str = "/proxy";
+
+/// SVG images can contain scripts.
+const BLOCKED_CONTENT_TYPES: &[&str] = &["image/svg+xml"];
+
+pub fn service() -> impl dev::HttpServiceFactory {
+    web::resource(format!("{}/{{signature}}/{{url}}", PROXY_PATH)).route(web::get().to(get))
+}
+
+/// What a value is signed for, so a signature can't be used for anything
+/// else.
+#[derive(Clone, Copy, Debug)]
+pub enum Signed {
+    Image,
+    Favicon,
+}
+
+impl Signed {
+    fn domain(self) -> &'static [u8] {
+        match self {
+            Signed::Image => b"image\0",
+            Signed::Favicon => b"favicon\0",
+        }
+    }
+}
+
+pub struct ImageProxy {
+    key: Vec<u8>,
+    public_url: String,
+    http_client: reqwest::Client,
+    read_timeout: std::time::Duration,
+    max_size: usize,
+    cache: Option<Cache>,
+}
+
+/// A proxied image.
+#[derive(Debug)]
+pub struct Image {
+    pub content_type: String,
+    pub body: Vec<u8>,
+}
+
+impl ImageProxy {
+    pub fn new(cfg: &Config) -> reqwest::Result<Self> {
+        let key = match &cfg.secret_key {
+            Some(secret_key) => Sha256::new()
+                .chain(b"image-proxy:")
+                .chain(secret_key.as_bytes())
+                .finalize()
+                .to_vec(),
+            None => {
+                log::warn!("FREADER_SECRET_KEY is not set: signed URLs expire on restart");
+                rand::thread_rng().gen::<[u8; 32]>().to_vec()
+            }
+        };
+
+        let cache = cfg
+            .image_cache_dir
+            .as_ref()
+            .map(|dir| Cache::new(PathBuf::from(dir), cfg.image_cache_max_size));
+
+        Ok(Self {
+            key,
+            public_url: cfg.public_url.trim_end_matches('/').to_owned(),
+            http_client: FeedManager::build_client(cfg, cfg.proxy.as_deref(), false)?,
+            read_timeout: cfg.fetch_read_timeout,
+            max_size: cfg.image_proxy_max_size,
+            cache,
+        })
+    }
+
+    fn mac(&self, signed: Signed, value: &str) -> HmacSha256 {
+        let mut mac = HmacSha256::new_varkey(&self.key).expect("HMAC accepts any key size");
+        mac.update(signed.domain());
+        mac.update(value.as_bytes());
+        mac
+    }
+
+    /// Sign `value`, for URLs clients load without authenticating.
+    pub fn sign(&self, signed: Signed, value: &str) -> String {
+        let signature = self.mac(signed, value).finalize().into_bytes();
+
+        base64::encode_config(&signature, base64::URL_SAFE_NO_PAD)
+    }
+
+    /// Whether `signature` is the one of `value` signed for `signed`.
+    pub fn check_signature(&self, signed: Signed, value: &str, signature: &str) -> bool {
+        match base64::decode_config(signature, base64::URL_SAFE_NO_PAD) {
+            Ok(signature) => self.mac(signed, value).verify(&signature).is_ok(),
+            Err(_) => false,
+        }
+    }
+
+    /// Get the proxied version of `url`, if it can be proxied.
+    pub fn proxy_url(&self, url: &str) -> Option<String> {
+        if !(url.starts_with("http://") || url.starts_with("https://")) {
+            return None;
+        }
+
+        Some(format!(
+            "{}{}/{}/{}",
+            self.public_url,
+            PROXY_PATH,
+            self.sign(Signed::Image, url),
+            base64::encode_config(url, base64::URL_SAFE_NO_PAD),
+        ))
+    }
+
+    /// Get the URL from a proxied URL's parts, if the signature is valid.
+    fn verify(&self, signature: &str, encoded_url: &str) -> Option<String> {
+        let url = base64::decode_config(encoded_url, base64::URL_SAFE_NO_PAD).ok()?;
+        let url = String::from_utf8(url).ok()?;
+
+        if self.check_signature(Signed::Image, &url, signature) {
+            Some(url)
+        } else {
+            None
+        }
+    }
+
+    /// Send a GET request for `url`, following redirects.
+    ///
+    /// Every URL is checked before the request, so a signed URL can't reach
+    /// private addresses through a redirect.
+    async fn send(&self, url: &str) -> Result<reqwest::Response, String> {
+        let mut url = reqwest::Url::parse(url).map_err(|e| e.to_string())?;
+
+        for _ in 0..=MAX_REDIRECTS {
+            check_public_url(&url).await?;
+
+            let resp = self
+                .http_client
+                .get(url.clone())
+                .send()
+                .await
+                .map_err(|e| e.to_string())?;
+            if !resp.status().is_redirection() {
+                return resp.error_for_status().map_err(|e| e.to_string());
+            }
+
+            url = resp
+                .headers()
+                .get(reqwest::header::LOCATION)
+                .and_then(|location| location.to_str().ok())
+                .and_then(|location| resp.url().join(location).ok())
+                .ok_or_else(|| format!("Invalid redirect from {}", resp.url()))?;
+        }
+
+        Err(format!("Too many redirects for {}", url))
+    }
+
+    async fn fetch(&self, url: &str) -> Result<Image, &'static str> {
+        let fetch_error = |e: String| {
+            log::error!("{}", e);
+            "Could not fetch image."
+        };
+
+        let mut resp = self.send(url).await.map_err(fetch_error)?;
+
+        let content_type = resp
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|val| val.to_str().ok())
+            .map(|val| val.trim().to_lowercase())
+            .unwrap_or_default();
+
+        if !is_allowed_image(&content_type) {
+            log::error!("{} is not an allowed image: {:?}", url, content_type);
+            return Err("Not an allowed image.");
+        }
+
+        if matches!(resp.content_length(), Some(len) if len > self.max_size as u64) {
+            log::error!("{} is too big: {:?} bytes", url, resp.content_length());
+            return Err("Image is too big.");
+        }
+
+        let mut body = Vec::new();
+        while let Some(chunk) = FeedManager::read_chunk(&mut resp, self.read_timeout)
+            .await
+            .map_err(fetch_error)?
+        {
+            if body.len() + chunk.len() > self.max_size {
+                log::error!("{} is too big: over {} bytes", url, self.max_size);
+                return Err("Image is too big.");
+            }
+
+            body.extend_from_slice(&chunk);
+        }
+
+        Ok(Image { content_type, body })
+    }
+
+    /// Get an image from the cache, or fetch it.
+    pub async fn get(&self, url: &str) -> Result<Image, &'static str> {
+        let cache = match &self.cache {
+            Some(cache) => cache.clone(),
+            None => return self.fetch(url).await,
+        };
+
+        let cached = {
+            let cache = cache.clone();
+            let url = url.to_owned();
+            web::block(move || cache.read(&url)).await
+        };
+        if let Ok(image) = cached {
+            return Ok(image);
+        }
+
+        let image = self.fetch(url).await?;
+
+        let url = url.to_owned();
+        let content_type = image.content_type.clone();
+        let body = image.body.clone();
+        actix_web::rt::spawn(async move {
+            let result = web::block(move || cache.write(&url, &content_type, &body)).await;
+            if let Err(e) = result {
+                log::error!("Could not cache image: {}", e);
+            }
+        });
+
+        Ok(image)
+    }
+}
+
+fn is_allowed_image(content_type: &str) -> bool {
+    let essence = content_type.split(';').next().unwrap_or("").trim();
+
+    essence.starts_with("image/") && !BLOCKED_CONTENT_TYPES.contains(&essence)
+}
+
+/// Ensure `url` is HTTP and its host only resolves to public addresses.
+///
+/// reqwest resolves the host again when connecting, so a host changing its
+/// addresses in between isn't caught.
+async fn check_public_url(url: &reqwest::Url) -> Result<(), String> {
+    if url.scheme() != "http" && url.scheme() != "https" {
+        return Err(format!("Not an HTTP URL: {}", url));
+    }
+
+    let host = url
+        .host_str()
+        .ok_or_else(|| format!("Missing host: {}", url))?
+        .trim_start_matches('[')
+        .trim_end_matches(']');
+    let port = url.port_or_known_default().unwrap_or(80);
+
+    let addrs: Vec<_> = tokio::net::lookup_host((host, port))
+        .await
+        .map_err(|e| format!("Could not resolve {}: {}", host, e))?
+        .collect();
+    if addrs.is_empty() {
+        return Err(format!("Could not resolve {}", host));
+    }
+
+    match addrs.iter().find(|addr| !is_public(addr.ip())) {
+        Some(addr) => Err(format!("{} is not a public address: {}", host, addr.ip())),
+        None => Ok(()),
+    }
+}
+
+/// Whether `ip` is reachable on the internet, as opposed to loopback,
+/// private, link-local and other special addresses.
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            let is_shared = a == 100 && (b & 0xc0) == 64;
+
+            !(ip.is_unspecified()
+                || ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_multicast()
+                || is_shared
+                || a == 0)
+        }
+        IpAddr::V6(ip) => {
+            if ip.is_unspecified() || ip.is_loopback() || ip.is_multicast() {
+                return false;
+            }
+            // IPv4-mapped and compatible addresses
+            if let Some(ipv4) = ip.to_ipv4() {
+                return is_public(IpAddr::V4(ipv4));
+            }
+
+            let first = ip.segments()[0];
+            let is_unique_local = (first & 0xfe00) == 0xfc00;
+            let is_link_local = (first & 0xffc0) == 0xfe80;
+
+            !(is_unique_local || is_link_local)
+        }
+    }
+}
+
+/// Images stored on disk, the least recently used ones are removed over
+/// `max_size`.
+///
+/// Each file contains the content type on the first line, then the image.
+#[derive(Debug, Clone)]
+struct Cache {
+    dir: PathBuf,
+    max_size: u64,
+    /// Loaded from the directory on first use.
+    index: Arc<Mutex<Option<CacheIndex>>>,
+}
+
+impl Cache {
+    fn new(dir: PathBuf, max_size: u64) -> Self {
+        Self {
+            dir,
+            max_size,
+            index: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    fn file_name(url: &str) -> String {
+        let hash = Sha256::digest(url.as_bytes());
+
+        hash.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    fn read(&self, url: &str) -> io::Result<Image> {
+        let name = Self::file_name(url);
+
+        let mut file = std::fs::File::open(self.dir.join(&name))?;
+        let mut data = Vec::new();
+        file.read_to_end(&mut data)?;
+
+        let invalid_data = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message);
+
+        let split = data
+            .iter()
+            .position(|b| *b == b'\n')
+            .ok_or_else(|| invalid_data("Missing content type"))?;
+        let body = data.split_off(split + 1);
+        let content_type = String::from_utf8(data[..split].to_vec())
+            .map_err(|_| invalid_data("Invalid content type"))?;
+        if !is_allowed_image(&content_type) {
+            return Err(invalid_data("Not an allowed image"));
+        }
+
+        self.with_index(|index| index.touch(&name))?;
+
+        Ok(Image { content_type, body })
+    }
+
+    /// Store an image, replacing the file at once so readers never see a
+    /// partial one.
+    fn write(&self, url: &str, content_type: &str, body: &[u8]) -> io::Result<()> {
+        std::fs::create_dir_all(&self.dir)?;
+
+        let name = Self::file_name(url);
+        let temp_path = self.dir.join(format!(
+            "{}{}{:016x}",
+            name,
+            TEMP_SUFFIX,
+            rand::random::<u64>()
+        ));
+
+        let result = Self::write_file(&temp_path, content_type, body)
+            .and_then(|()| std::fs::rename(&temp_path, self.dir.join(&name)));
+        if let Err(e) = result {
+            let _ = std::fs::remove_file(&temp_path);
+            return Err(e);
+        }
+
+        let size = (content_type.len() + 1 + body.len()) as u64;
+        let evicted = self.with_index(|index| index.insert(name, size, self.max_size))?;
+
+        for name in evicted {
+            match std::fs::remove_file(self.dir.join(name)) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    fn write_file(path: &Path, content_type: &str, body: &[u8]) -> io::Result<()> {
+        let mut file = std::fs::File::create(path)?;
+        file.write_all(content_type.as_bytes())?;
+        file.write_all(b"\n")?;
+        file.write_all(body)
+    }
+
+    fn with_index<T>(&self, f: impl FnOnce(&mut CacheIndex) -> T) -> io::Result<T> {
+        let mut index = self.index.lock().unwrap();
+        if index.is_none() {
+            *index = Some(CacheIndex::load(&self.dir)?);
+        }
+
+        Ok(f(index.as_mut().expect("index is loaded")))
+    }
+}
+
+/// Suffix of files being written to the cache.
+const TEMP_SUFFIX: &str = ".tmp-";
+
+/// Cached files by last use.
+#[derive(Debug, Default)]
+struct CacheIndex {
+    total_size: u64,
+    /// Incremented on each use.
+    clock: u64,
+    /// Last use and size of each file.
+    files: HashMap<String, (u64, u64)>,
+    /// Files by last use.
+    by_use: BTreeMap<u64, String>,
+}
+
+impl CacheIndex {
+    /// List the files of `dir`, the ones modified last being the most
+    /// recently used.
+    ///
+    /// Files left over by interrupted writes are removed.
+    fn load(dir: &Path) -> io::Result<Self> {
+        let mut files = Vec::new();
+
+        let entries = match std::fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(e),
+        };
+        for entry in entries {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            let name = match entry.file_name().into_string() {
+                Ok(name) if metadata.is_file() => name,
+                _ => continue,
+            };
+
+            if name.contains(TEMP_SUFFIX) {
+                let _ = std::fs::remove_file(entry.path());
+                continue;
+            }
+
+            files.push((metadata.modified()?, metadata.len(), name));
+        }
+
+        files.sort();
+
+        let mut index = Self::default();
+        for (_, size, name) in files {
+            index.insert(name, size, u64::MAX);
+        }
+
+        Ok(index)
+    }
+
+    fn touch(&mut self, name: &str) {
+        if let Some((last_use, _)) = self.files.get_mut(name) {
+            self.clock += 1;
+
+            let name = self.by_use.remove(last_use).unwrap_or_default();
+            *last_use = self.clock;
+            self.by_use.insert(self.clock, name);
+        }
+    }
+
+    /// Add a file, and remove the least recently used ones until the total
+    /// size fits in `max_size`.
+    ///
+    /// Result is the names of the removed files.
+    fn insert(&mut self, name: String, size: u64, max_size: u64) -> Vec<String> {
+        self.remove(&name);
+
+        self.clock += 1;
+        self.total_size += size;
+        self.files.insert(name.clone(), (self.clock, size));
+        self.by_use.insert(self.clock, name);
+
+        let mut evicted = Vec::new();
+        while self.total_size > max_size {
+            let oldest = match self.by_use.values().next() {
+                Some(oldest) => oldest.clone(),
+                None => break,
+            };
+
+            self.remove(&oldest);
+            evicted.push(oldest);
+        }
+
+        evicted
+    }
+
+    fn remove(&mut self, name: &str) {
+        if let Some((last_use, size)) = self.files.remove(name) {
+            self.by_use.remove(&last_use);
+            self.total_size -= size;
+        }
+    }
+}
+
+
+#[derive(Debug, serde::Deserialize)]
+struct ProxyPath {
+    signature: String,
+    url: String,
+}
+
+async fn get(data: web::Data<AppData>, path: web::Path<ProxyPath>) -> HttpResponse {
+    let url = match data.image_proxy.verify(&path.signature, &path.url) {
+        Some(url) => url,
+        None => return HttpResponse::Forbidden().body("Invalid signature"),
+    };
+
+    match data.image_proxy.get(&url).await {
+        Ok(image) => HttpResponse::Ok()
+            .content_type(image.content_type)
+            .header(header::CACHE_CONTROL, "public, max-age=604800, immutable")
+            .header(header::CONTENT_SECURITY_POLICY, "default-src 'none'")
+            .header(header::X_CONTENT_TYPE_OPTIONS, "nosniff")
+            .body(image.body),
+        Err(e) => HttpResponse::BadGateway().body(e),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image_proxy() -> ImageProxy {
+        ImageProxy {
+            key: b"key".to_vec(),
+            public_url: "https://freader.example.com".to_owned(),
+            http_client: reqwest::Client::new(),
+            read_timeout: std::time::Duration::from_secs(1),
+            max_size: 1024,
+            cache: None,
+        }
+    }
+
+    /// Split a proxied URL into its signature and encoded URL.
+    fn split(proxied: &str) -> (String, String) {
+        let path = proxied
+            .strip_prefix("https://freader.example.com/proxy/")
+            .unwrap();
+        let mut parts = path.splitn(2, '/');
+
+        (
+            parts.next().unwrap().to_owned(),
+            parts.next().unwrap().to_owned(),
+        )
+    }
+
+    #[test]
+    fn proxy_url_round_trip() {
+        let proxy = image_proxy();
+        let url = "https://example.com/image.png?size=large";
+
+        let (signature, encoded_url) = split(&proxy.proxy_url(url).unwrap());
+
+        assert_eq!(proxy.verify(&signature, &encoded_url).as_deref(), Some(url));
+    }
+
+    #[test]
+    fn tampered_url_is_rejected() {
+        let proxy = image_proxy();
+        let (signature, encoded_url) =
+            split(&proxy.proxy_url("https://example.com/a.png").unwrap());
+        let (other_signature, other_url) =
+            split(&proxy.proxy_url("http://127.0.0.1/b.png").unwrap());
+
+        assert_eq!(proxy.verify(&signature, &other_url), None);
+        assert_eq!(proxy.verify(&other_signature, &encoded_url), None);
+        assert_eq!(proxy.verify("", &encoded_url), None);
+
+        let mut other_key = image_proxy();
+        other_key.key = b"other key".to_vec();
+        assert_eq!(other_key.verify(&signature, &encoded_url), None);
+    }
+
+    #[test]
+    fn signatures_are_only_valid_for_what_they_sign() {
+        let proxy = image_proxy();
+        let url = "https://example.com/a.png";
+        let (image_signature, encoded_url) = split(&proxy.proxy_url(url).unwrap());
+        let favicon_signature = proxy.sign(Signed::Favicon, url);
+
+        assert!(proxy.check_signature(Signed::Favicon, url, &favicon_signature));
+        assert!(!proxy.check_signature(Signed::Favicon, url, &image_signature));
+        assert_eq!(proxy.verify(&favicon_signature, &encoded_url), None);
+    }
+
+    #[test]
+    fn only_http_urls_are_proxied() {
+        let proxy = image_proxy();
+
+        assert_eq!(proxy.proxy_url("data:image/png;base64,AAAA"), None);
+        assert_eq!(proxy.proxy_url("file:///etc/passwd"), None);
+    }
+
+    #[test]
+    fn cache_evicts_least_recently_used() {
+        let mut index = CacheIndex::default();
+
+        assert!(index.insert("a".to_owned(), 10, 25).is_empty());
+        assert!(index.insert("b".to_owned(), 10, 25).is_empty());
+        index.touch("a");
+
+        assert_eq!(index.insert("c".to_owned(), 10, 25), vec!["b".to_owned()]);
+        assert_eq!(index.total_size, 20);
+
+        // Replacing a file doesn't count it twice
+        assert!(index.insert("c".to_owned(), 15, 25).is_empty());
+        assert_eq!(index.total_size, 25);
+    }
+
+    #[test]
+    fn cache_checks_content_type() {
+        let dir = std::env::temp_dir().join(format!("freader-test-{:016x}", rand::random::<u64>()));
+        let cache = Cache::new(dir.clone(), 1024);
+        let url = "https://example.com/image.png";
+
+        cache.write(url, "image/png", b"png").unwrap();
+        let image = cache.read(url).unwrap();
+        assert_eq!(image.content_type, "image/png");
+        assert_eq!(image.body, b"png");
+
+        cache.write(url, "image/svg+xml", b"<svg/>").unwrap();
+        assert!(cache.read(url).is_err());
+
+        std::fs::write(dir.join(Cache::file_name(url)), "text/html\n<script>").unwrap();
+        assert!(cache.read(url).is_err());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn public_addresses() {
+        for ip in &["93.184.216.34", "2606:2800:220:1:248:1893:25c8:1946"] {
+            assert!(is_public(ip.parse().unwrap()), "{}", ip);
+        }
+    }
+
+    #[test]
+    fn non_public_addresses() {
+        for ip in &[
+            "127.0.0.1",
+            "10.0.0.1",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::1",
+            "::",
+            "::ffff:127.0.0.1",
+            "::ffff:169.254.169.254",
+            "fc00::1",
+            "fd12:3456::1",
+            "fe80::1",
+        ] {
+            assert!(!is_public(ip.parse().unwrap()), "{}", ip);
+        }
+    }
+}
@@ -7,16 +7,30 @@ use actix_web::{middleware, web, App, HttpServer};
 pub mod appdata;
 pub mod auth;
 pub mod config;
+pub mod credentials;
 pub mod db;
+pub mod downloader;
+pub mod events;
 pub mod feed_manager;
+pub mod html;
+pub mod image_proxy;
 pub mod opml;
 pub mod prelude;
 pub mod reader;
+pub mod resolvers;
+pub mod retention;
+pub mod rules;
 pub mod updater;
 pub mod utils;
+pub mod xml_base;
 
+use db::models::{NewRule, Operation};
+use downloader::Downloader;
+use events::Events;
 use feed_manager::FeedManager;
+use image_proxy::ImageProxy;
 use prelude::*;
+use retention::RetentionPolicy;
 use updater::Updater;
 
 const ENV_FILENAME: &str = "freader.env";
@@ -52,11 +66,44 @@ async fn main() -> std::io::Result<()> {
         }
     };
 
-    let feed_manager = FeedManager::new(db.clone());
+    let events = Events::new();
 
-    let updater = Updater::new(db.clone(), feed_manager.clone());
+    let feed_manager = match FeedManager::new(&cfg, db.clone(), events.clone()) {
+        Ok(feed_manager) => feed_manager,
+        Err(err) => {
+            log::error!("Could not create HTTP client: {}", err);
+            std::process::exit(2);
+        }
+    };
+
+    let image_proxy = match ImageProxy::new(&cfg) {
+        Ok(image_proxy) => image_proxy,
+        Err(err) => {
+            log::error!("Could not create HTTP client: {}", err);
+            std::process::exit(2);
+        }
+    };
 
-    let data = web::Data::new(AppData::new(cfg.clone(), db, feed_manager));
+    let updater = Updater::new(&cfg, db.clone(), events.clone(), feed_manager.clone());
+    let downloader = Downloader::new(&cfg, db.clone(), feed_manager.clone());
+
+    let data = web::Data::new(AppData::new(
+        cfg.clone(),
+        db,
+        events,
+        feed_manager,
+        image_proxy,
+    ));
+
+    // Contents must be safe before anything is served
+    match data.feed_manager.reprocess_pending_items().await {
+        Ok(0) => (),
+        Ok(count) => log::info!("Processed the content of {} items", count),
+        Err(err) => {
+            log::error!("Could not process items: {}", err);
+            std::process::exit(2);
+        }
+    }
 
     if let Some(ecode) = handle_cli_args(&data).await? {
         std::process::exit(ecode);
@@ -69,6 +116,8 @@ async fn main() -> std::io::Result<()> {
             .wrap(middleware::Compress::default())
             .wrap(middleware::Logger::default())
             .service(auth::service())
+            .service(image_proxy::service())
+            .service(reader::favicon_service())
             .service(reader::service())
             .default_service(web::route().to(
                 |_req: actix_web::HttpRequest, _body: actix_web::web::Bytes| {
@@ -115,6 +164,7 @@ async fn main() -> std::io::Result<()> {
     }
 
     updater.start();
+    downloader.start();
 
     server.run().await
 }
@@ -124,7 +174,7 @@ async fn main() -> std::io::Result<()> {
 /// Returns an exit code if the program should stop,
 /// otherwise returns `None`.
 async fn handle_cli_args(data: &AppData) -> std::io::Result<Option<i32>> {
-    let mut args = std::env::args().into_iter().skip(1);
+    let mut args = std::env::args().into_iter().skip(1).peekable();
 
     while let Some(arg) = args.next() {
         match arg.as_str() {
@@ -143,6 +193,240 @@ async fn handle_cli_args(data: &AppData) -> std::io::Result<Option<i32>> {
 
                 opml::import(&file, &mut data.db.clone()).await?;
             }
+            "--purge" => {
+                let dry_run = args.peek().map(String::as_str) == Some("--dry-run");
+                if dry_run {
+                    args.next();
+                }
+
+                let policy = RetentionPolicy::from_config(&data.cfg);
+                let count = data
+                    .db
+                    .clone()
+                    .purge_items(policy, dry_run)
+                    .await
+                    .map_err(io_error)?
+                    .len();
+
+                if dry_run {
+                    println!("{} items would be purged", count);
+                } else {
+                    println!("Purged {} items", count);
+                }
+
+                return Ok(Some(0));
+            }
+            "--label-retention" => {
+                let values: Vec<String> = args.by_ref().take(3).collect();
+                let (label, read_days, max_items) = match values.as_slice() {
+                    [label, read_days, max_items] => {
+                        (label.clone(), read_days.as_str(), max_items.as_str())
+                    }
+                    _ => {
+                        eprintln!("Missing values for {}", arg);
+                        return Ok(Some(1));
+                    }
+                };
+
+                let parse = |value: &str| match value {
+                    "-" => Ok(None),
+                    _ => match value.parse::<i32>() {
+                        Ok(value) if value >= 0 => Ok(Some(value)),
+                        _ => Err(()),
+                    },
+                };
+                let (read_days, max_items) = match (parse(read_days), parse(max_items)) {
+                    (Ok(read_days), Ok(max_items)) => (read_days, max_items),
+                    _ => {
+                        eprintln!("Invalid values for {}", arg);
+                        return Ok(Some(1));
+                    }
+                };
+
+                data.db
+                    .clone()
+                    .transform_category(label, move |category| {
+                        category.keep_read_days = read_days;
+                        category.keep_max_items = max_items;
+                    })
+                    .await
+                    .map_err(io_error)?;
+            }
+            "--reprocess" => {
+                let count = data
+                    .feed_manager
+                    .reprocess_items()
+                    .await
+                    .map_err(io_error)?;
+                println!("Reprocessed {} items", count);
+
+                return Ok(Some(0));
+            }
+            "--list-rules" => {
+                for rule in data.db.clone().get_rules().await.map_err(io_error)? {
+                    let scope = match (rule.subscription_id, rule.category) {
+                        (Some(id), _) => format!(" (feed {})", id.inner()),
+                        (None, Some(label)) => format!(" (label {})", label),
+                        (None, None) => String::new(),
+                    };
+                    let action = match rule.label {
+                        Some(label) => format!("{}:{}", rule.action, label),
+                        None => rule.action,
+                    };
+                    let kind = if rule.is_regex { "regex" } else { "keyword" };
+
+                    println!(
+                        "{}: {} {} {:?} -> {}{}",
+                        rule.name, rule.field, kind, rule.pattern, action, scope
+                    );
+                }
+
+                return Ok(Some(0));
+            }
+            "--add-rule" => {
+                let values: Vec<String> = args.by_ref().take(4).collect();
+                let (name, field, pattern, action) = match values.as_slice() {
+                    [name, field, pattern, action] => (name, field, pattern, action),
+                    _ => {
+                        eprintln!("Missing values for {}", arg);
+                        return Ok(Some(1));
+                    }
+                };
+
+                let mut action = action.splitn(2, ':');
+                let mut new_rule = NewRule {
+                    name: name.clone(),
+                    field: field.clone(),
+                    pattern: pattern.clone(),
+                    is_regex: false,
+                    subscription_id: None,
+                    category: None,
+                    action: action.next().unwrap_or_default().to_owned(),
+                    label: action.next().map(str::to_owned),
+                };
+
+                // Optional flags
+                loop {
+                    match args.peek().map(String::as_str) {
+                        Some("--regex") => new_rule.is_regex = true,
+                        Some("--feed") => {
+                            args.next();
+                            match args.peek().and_then(|id| id.parse().ok()) {
+                                Some(id) => new_rule.subscription_id = Some(db::Id::from_raw(id)),
+                                None => {
+                                    eprintln!("Invalid value for --feed");
+                                    return Ok(Some(1));
+                                }
+                            }
+                        }
+                        Some("--label") => {
+                            args.next();
+                            new_rule.category = args.peek().cloned();
+                        }
+                        _ => break,
+                    }
+                    args.next();
+                }
+
+                if let Err(e) = rules::validate(&new_rule) {
+                    eprintln!("Invalid rule: {}", e);
+                    return Ok(Some(1));
+                }
+
+                data.db
+                    .clone()
+                    .create_rule(new_rule)
+                    .await
+                    .map_err(io_error)?;
+            }
+            "--remove-rule" => {
+                let name = match args.next() {
+                    Some(x) => x,
+                    None => {
+                        eprintln!("Missing value for {}", arg);
+                        return Ok(Some(1));
+                    }
+                };
+
+                if !data.db.clone().remove_rule(name).await.map_err(io_error)? {
+                    eprintln!("Unknown rule");
+                    return Ok(Some(1));
+                }
+            }
+            "--list-operations" => {
+                let min_date = Operation::min_date(data.cfg.undo_window);
+                let operations = data
+                    .db
+                    .clone()
+                    .get_operations(min_date)
+                    .await
+                    .map_err(io_error)?;
+
+                for operation in operations {
+                    let mut changes = Vec::new();
+                    match operation.is_read {
+                        Some(true) => changes.push("read"),
+                        Some(false) => changes.push("unread"),
+                        None => (),
+                    }
+                    match operation.is_starred {
+                        Some(true) => changes.push("starred"),
+                        Some(false) => changes.push("unstarred"),
+                        None => (),
+                    }
+
+                    let reverted = if operation.is_reverted {
+                        " (reverted)"
+                    } else {
+                        ""
+                    };
+
+                    println!(
+                        "{}: {} {} marked {} items {}{}",
+                        operation.id.inner(),
+                        operation.created_at.format("%Y-%m-%d %H:%M:%S UTC"),
+                        operation.kind,
+                        operation.item_count,
+                        changes.join(" and "),
+                        reverted
+                    );
+                }
+
+                return Ok(Some(0));
+            }
+            "--revert-operation" => {
+                let id = match args.next().and_then(|id| id.parse().ok()) {
+                    Some(id) => db::Id::from_raw(id),
+                    None => {
+                        eprintln!("Missing or invalid value for {}", arg);
+                        return Ok(Some(1));
+                    }
+                };
+
+                let min_date = Operation::min_date(data.cfg.undo_window);
+                let restored = data
+                    .db
+                    .clone()
+                    .revert_operation(id, min_date)
+                    .await
+                    .map_err(io_error)?;
+
+                match restored {
+                    Some(ids) => println!("Restored {} items", ids.len()),
+                    None => {
+                        eprintln!("Unknown, expired or reverted operation");
+                        return Ok(Some(1));
+                    }
+                }
+            }
+            "--apply-rules" => {
+                let ids = rules::reapply(&mut data.db.clone())
+                    .await
+                    .map_err(io_error)?;
+                println!("Rules matched {} items", ids.len());
+
+                return Ok(Some(0));
+            }
             _ => {
                 eprintln!("Unknown argument: {}", arg);
                 print_usage();
@@ -154,6 +438,15 @@ async fn handle_cli_args(data: &AppData) -> std::io::Result<Option<i32>> {
     Ok(None)
 }
 
+fn io_error(e: db::Error) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::Other, e.to_string())
+}
+
 fn print_usage() {
-    println!("USAGE: freader [-h | --help] [--import OPML]");
+    println!("USAGE: freader [-h | --help] [--import OPML] [--purge [--dry-run]] [--reprocess]");
+    println!("               [--label-retention LABEL READ_DAYS|- MAX_ITEMS|-]");
+    println!("               [--list-rules] [--remove-rule NAME] [--apply-rules]");
+    println!("               [--list-operations] [--revert-operation ID]");
+    println!("               [--add-rule NAME FIELD PATTERN ACTION[:LABEL]");
+    println!("                           [--regex] [--feed ID | --label LABEL]]");
 }
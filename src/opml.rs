@@ -55,6 +55,14 @@ fn import_outlines<'a>(
                     site_url,
                     next_refresh: chrono::Utc.timestamp(0, 0).naive_utc(),
                     error_count: 0,
+                    proxy: None,
+                    credentials: None,
+                    keep_read_days: None,
+                    keep_max_items: None,
+                    fetch_full_content: false,
+                    content_selector: None,
+                    download_enclosures: false,
+                    keep_downloads: None,
                 })
                 .await;
 
@@ -0,0 +1,190 @@
+use actix_web::dev::HttpServiceFactory;
+use actix_web::{web, HttpResponse};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+use super::favicon;
+use super::stream::{item_id, items_categories, ItemId, StreamId};
+use super::subscription::{LabelId, SubscriptionId};
+use crate::db::models::{Change, Item};
+use crate::prelude::*;
+
+pub fn service() -> impl HttpServiceFactory {
+    web::resource("/changes").route(web::get().to(changes))
+}
+
+
+#[derive(Debug, Deserialize)]
+struct ChangesQuery {
+    /// `changeSeq` of the previous response, 0 to get everything.
+    #[serde(default)]
+    since: i64,
+    #[serde(rename = "n", default = "ChangesQuery::default_count")]
+    count: usize,
+}
+
+impl ChangesQuery {
+    /// Maximum number of changes in a response.
+    const MAX_COUNT: usize = 10000;
+
+    fn default_count() -> usize {
+        1000
+    }
+
+    /// Number of changes to get, at least one so clients make progress.
+    fn count(&self) -> usize {
+        self.count.max(1).min(Self::MAX_COUNT)
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct ChangesResponse<'a> {
+    /// Latest change included in the response.
+    #[serde(rename = "changeSeq")]
+    change_seq: i64,
+    /// Whether there are changes after `change_seq`.
+    more: bool,
+    items: Vec<ChangesResponseItem>,
+    #[serde(rename = "deletedItems")]
+    deleted_items: Vec<ChangesResponseDeletedItem>,
+    subscriptions: Vec<ChangesResponseSubscription<'a>>,
+    #[serde(rename = "deletedSubscriptions")]
+    deleted_subscriptions: Vec<SubscriptionId>,
+    labels: Vec<LabelId>,
+    #[serde(rename = "deletedLabels")]
+    deleted_labels: Vec<LabelId>,
+}
+
+#[derive(Debug, Serialize)]
+struct ChangesResponseItem {
+    #[serde(serialize_with = "item_id::long")]
+    id: ItemId,
+    #[serde(rename = "streamId")]
+    stream_id: SubscriptionId,
+    updated: i64,
+    categories: Vec<StreamId>,
+}
+
+#[derive(Debug, Serialize)]
+struct ChangesResponseDeletedItem {
+    #[serde(serialize_with = "item_id::long")]
+    id: ItemId,
+}
+
+#[derive(Debug, Serialize)]
+struct ChangesResponseSubscription<'a> {
+    id: SubscriptionId,
+    title: &'a str,
+    #[serde(rename = "htmlUrl", skip_serializing_if = "Option::is_none")]
+    site_url: &'a Option<String>,
+    #[serde(rename = "iconUrl", skip_serializing_if = "Option::is_none")]
+    icon_url: Option<String>,
+    categories: Vec<LabelId>,
+}
+
+/// Get the items, subscriptions and labels changed since a sequence number.
+///
+/// Only the state of changed items is included, their content can then be
+/// fetched with `stream/items/contents`.
+async fn changes(
+    data: web::Data<AppData>,
+    query: web::Query<ChangesQuery>,
+) -> actix_web::Result<HttpResponse> {
+    let mut db = data.db.clone();
+
+    let count = query.count();
+    let changes = db.get_changes(query.since, count).await?;
+    let change_seq = changes
+        .last()
+        .map_or(query.since, |change| change.change_seq);
+    let more = changes.len() == count;
+
+    let mut item_ids = Vec::new();
+    let mut deleted_items = Vec::new();
+    let mut subscription_ids = HashSet::new();
+    let mut deleted_subscriptions = Vec::new();
+    let mut labels = Vec::new();
+    let mut deleted_labels = Vec::new();
+
+    for change in changes {
+        match (change.kind.as_str(), change.is_deleted) {
+            (Change::ITEM, false) => item_ids.push(change.object_id),
+            (Change::ITEM, true) => deleted_items.push(ChangesResponseDeletedItem {
+                id: ItemId(change.object_id),
+            }),
+            (Change::SUBSCRIPTION, false) => {
+                subscription_ids.insert(change.object_id);
+            }
+            (Change::SUBSCRIPTION, true) => {
+                deleted_subscriptions.push(SubscriptionId(change.object_id))
+            }
+            (Change::CATEGORY, false) => labels.extend(change.name.map(LabelId)),
+            (Change::CATEGORY, true) => deleted_labels.extend(change.name.map(LabelId)),
+            _ => log::warn!("Unknown change kind: {}", change.kind),
+        }
+    }
+
+    let pairs = db.get_items_and_subscriptions(item_ids).await?;
+    let changed_items: Vec<&Item> = pairs.iter().map(|(item, _)| item).collect();
+    let mut categories = items_categories(&mut db, &changed_items).await?;
+
+    let items = changed_items
+        .iter()
+        .map(|item| ChangesResponseItem {
+            id: ItemId(item.id),
+            stream_id: SubscriptionId(item.subscription_id),
+            updated: item.updated.timestamp(),
+            categories: categories.remove(&item.id).unwrap_or_default(),
+        })
+        .collect();
+
+    let subscriptions: Vec<_> = db
+        .get_subscriptions()
+        .await?
+        .into_iter()
+        .filter(|subscription| subscription_ids.contains(&subscription.id))
+        .collect();
+    let favicon_ids: HashSet<db::Id> = db
+        .get_favicon_subscription_ids()
+        .await?
+        .into_iter()
+        .collect();
+
+    let mut subscription_labels: Vec<Vec<LabelId>> = Vec::with_capacity(subscriptions.len());
+    for subscription in &subscriptions {
+        let categories = db.get_subscription_categories(subscription.id).await?;
+        subscription_labels.push(
+            categories
+                .into_iter()
+                .map(|category| LabelId(category.name))
+                .collect(),
+        );
+    }
+
+    let subscriptions = subscriptions
+        .iter()
+        .zip(subscription_labels)
+        .map(|(subscription, categories)| ChangesResponseSubscription {
+            id: SubscriptionId(subscription.id),
+            title: &subscription.title,
+            site_url: &subscription.site_url,
+            icon_url: if favicon_ids.contains(&subscription.id) {
+                Some(favicon::url(&data, subscription.id))
+            } else {
+                None
+            },
+            categories,
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok().json(ChangesResponse {
+        change_seq,
+        more,
+        items,
+        deleted_items,
+        subscriptions,
+        deleted_subscriptions,
+        labels,
+        deleted_labels,
+    }))
+}
@@ -0,0 +1,55 @@
+use actix_files::NamedFile;
+use actix_web::dev::HttpServiceFactory;
+use actix_web::http::header::{ContentDisposition, DispositionParam, DispositionType};
+use actix_web::{web, HttpRequest, HttpResponse};
+
+use crate::downloader::Downloader;
+use crate::prelude::*;
+
+const ENCLOSURE_PATH: &str = "/reader/api/0/enclosure";
+
+pub fn service() -> impl HttpServiceFactory {
+    web::resource("/enclosure/{id}").route(web::get().to(get))
+}
+
+/// Get the URL of a downloaded enclosure.
+pub fn url(cfg: &Config, id: db::Id) -> String {
+    format!(
+        "{}{}/{}",
+        cfg.public_url.trim_end_matches('/'),
+        ENCLOSURE_PATH,
+        id.inner()
+    )
+}
+
+/// Serve a downloaded enclosure, range requests are supported.
+async fn get(
+    req: HttpRequest,
+    data: web::Data<AppData>,
+    id: web::Path<db::Id>,
+) -> actix_web::Result<HttpResponse> {
+    let enclosure = match data.db.clone().get_enclosure(id.into_inner()).await? {
+        Some(enclosure) if enclosure.is_downloaded() => enclosure,
+        _ => return Ok(HttpResponse::NotFound().body("Enclosure not downloaded")),
+    };
+
+    let mut file = NamedFile::open(Downloader::path(&data.cfg, &enclosure))?;
+
+    if let Some(mime) = enclosure.mime_type.as_deref().and_then(|t| t.parse().ok()) {
+        file = file.set_content_type(mime);
+    }
+
+    // Files are named by ID, use the original name instead
+    let file_name = reqwest::Url::parse(&enclosure.url)
+        .ok()
+        .and_then(|url| url.path_segments()?.last().map(str::to_owned))
+        .filter(|name| !name.is_empty());
+    if let Some(file_name) = file_name {
+        file = file.set_content_disposition(ContentDisposition {
+            disposition: DispositionType::Inline,
+            parameters: vec![DispositionParam::Filename(file_name)],
+        });
+    }
+
+    file.into_response(&req)
+}
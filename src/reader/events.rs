@@ -0,0 +1,118 @@
+use actix_web::dev::{BodyEncoding, HttpServiceFactory};
+use actix_web::http::ContentEncoding;
+use actix_web::{web, HttpResponse};
+use serde::Serialize;
+use std::time::Duration;
+use tokio::sync::broadcast::RecvError;
+
+use super::stream::{item_id, ItemId};
+use super::subscription::SubscriptionId;
+use crate::events::Event;
+use crate::prelude::*;
+
+/// Comments are sent this often when nothing happens, so proxies don't
+/// close the connection.
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(30);
+
+pub fn service() -> impl HttpServiceFactory {
+    web::resource("/events").route(web::get().to(events))
+}
+
+
+#[derive(Debug, Serialize)]
+struct NewItemsData {
+    #[serde(rename = "streamId")]
+    stream_id: SubscriptionId,
+    count: usize,
+}
+
+#[derive(Debug, Serialize)]
+struct ItemsChangedData {
+    items: Vec<ItemsChangedDataItem>,
+}
+
+#[derive(Debug, Serialize)]
+struct ItemsChangedDataItem {
+    #[serde(serialize_with = "item_id::long")]
+    id: ItemId,
+}
+
+#[derive(Debug, Serialize)]
+struct SubscriptionData {
+    #[serde(rename = "streamId")]
+    stream_id: SubscriptionId,
+}
+
+/// Stream events as Server-Sent Events.
+///
+/// When events were missed because the client is too slow, a `resync` event
+/// is sent so it can reload everything.
+async fn events(data: web::Data<AppData>) -> HttpResponse {
+    let receiver = data.events.subscribe();
+
+    let stream = futures::stream::unfold(receiver, |mut receiver| async move {
+        let message = match tokio::time::timeout(KEEP_ALIVE_INTERVAL, receiver.recv()).await {
+            Ok(Ok(event)) => format_event(&event),
+            Ok(Err(RecvError::Lagged(count))) => {
+                log::debug!("Event stream missed {} events", count);
+                "event: resync\ndata: {}\n\n".to_owned()
+            }
+            Ok(Err(RecvError::Closed)) => return None,
+            Err(_) => ":\n\n".to_owned(),
+        };
+
+        Some((
+            Ok::<_, actix_web::Error>(web::Bytes::from(message)),
+            receiver,
+        ))
+    });
+
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .header("Cache-Control", "no-cache")
+        // Compressing would buffer events
+        .encoding(ContentEncoding::Identity)
+        .streaming(stream)
+}
+
+fn format_event(event: &Event) -> String {
+    let (name, data) = match event {
+        Event::NewItems {
+            subscription_id,
+            count,
+        } => (
+            "new-items",
+            serde_json::to_string(&NewItemsData {
+                stream_id: SubscriptionId(*subscription_id),
+                count: *count,
+            }),
+        ),
+        Event::ItemsChanged(ids) => (
+            "items-changed",
+            serde_json::to_string(&ItemsChangedData {
+                items: ids
+                    .iter()
+                    .map(|id| ItemsChangedDataItem { id: ItemId(*id) })
+                    .collect(),
+            }),
+        ),
+        Event::SubscriptionAdded(id) => (
+            "subscription-added",
+            serde_json::to_string(&SubscriptionData {
+                stream_id: SubscriptionId(*id),
+            }),
+        ),
+        Event::SubscriptionRemoved(id) => (
+            "subscription-removed",
+            serde_json::to_string(&SubscriptionData {
+                stream_id: SubscriptionId(*id),
+            }),
+        ),
+    };
+
+    format!(
+        "event: {}\ndata: {}\n\n",
+        name,
+        data.expect("event data is serializable")
+    )
+}
@@ -0,0 +1,59 @@
+use actix_web::dev::HttpServiceFactory;
+use actix_web::{http::header, web, HttpResponse};
+use serde::Deserialize;
+
+use crate::image_proxy::Signed;
+use crate::prelude::*;
+
+/// Favicons are used in `<img>` elements, which can't authenticate, so
+/// they are served outside of the API with signed URLs.
+const FAVICON_PATH: &str = "/favicon";
+
+pub fn service() -> impl HttpServiceFactory {
+    web::resource(format!("{}/{{id}}/{{signature}}", FAVICON_PATH)).route(web::get().to(get))
+}
+
+/// Get the URL of a subscription's favicon.
+pub fn url(data: &AppData, subscription_id: db::Id) -> String {
+    format!(
+        "{}{}/{}/{}",
+        data.cfg.public_url.trim_end_matches('/'),
+        FAVICON_PATH,
+        subscription_id.inner(),
+        data.image_proxy
+            .sign(Signed::Favicon, &subscription_id.inner().to_string()),
+    )
+}
+
+#[derive(Debug, Deserialize)]
+struct FaviconPath {
+    id: db::Id,
+    signature: String,
+}
+
+async fn get(
+    data: web::Data<AppData>,
+    path: web::Path<FaviconPath>,
+) -> actix_web::Result<HttpResponse> {
+    if !data.image_proxy.check_signature(
+        Signed::Favicon,
+        &path.id.inner().to_string(),
+        &path.signature,
+    ) {
+        return Ok(HttpResponse::Forbidden().body("Invalid signature"));
+    }
+
+    let favicon = data.db.clone().get_favicon(path.id).await?;
+
+    let (content_type, body) = match favicon.and_then(|f| Some((f.content_type?, f.data?))) {
+        Some(found) => found,
+        None => return Ok(HttpResponse::NotFound().body("No favicon")),
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(content_type)
+        .header(header::CACHE_CONTROL, "private, max-age=86400")
+        .header(header::CONTENT_SECURITY_POLICY, "default-src 'none'")
+        .header(header::X_CONTENT_TYPE_OPTIONS, "nosniff")
+        .body(body))
+}
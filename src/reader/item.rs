@@ -0,0 +1,60 @@
+use actix_web::dev::HttpServiceFactory;
+use actix_web::{web, HttpResponse};
+use serde::{Deserialize, Serialize};
+
+use super::stream::ItemId;
+use crate::html;
+use crate::prelude::*;
+
+pub fn service() -> impl HttpServiceFactory {
+    web::scope("/item").route("/full-content", web::post().to(full_content))
+}
+
+
+#[derive(Debug, Deserialize)]
+struct FullContentData {
+    #[serde(rename = "i")]
+    id: ItemId,
+}
+
+#[derive(Debug, Serialize)]
+struct FullContentResponse<'a> {
+    content: &'a str,
+}
+
+/// Download the full article of an item now, even if its subscription
+/// doesn't fetch full contents.
+async fn full_content(
+    data: web::Data<AppData>,
+    form: web::Form<FullContentData>,
+) -> actix_web::Result<HttpResponse> {
+    let pairs = data
+        .db
+        .clone()
+        .get_items_and_subscriptions(vec![form.id.0])
+        .await?;
+
+    let (item, subscription) = match pairs.into_iter().next() {
+        Some(pair) => pair,
+        None => return Ok(HttpResponse::NotFound().body("Item not found")),
+    };
+
+    let content = match data
+        .feed_manager
+        .fetch_full_content(&item, &subscription)
+        .await
+    {
+        Ok(content) => content,
+        Err(e) => return Ok(HttpResponse::BadGateway().body(e)),
+    };
+
+    let content = if data.cfg.image_proxy {
+        html::rewrite_image_urls(&content, |url| data.image_proxy.proxy_url(url))
+    } else {
+        content
+    };
+
+    Ok(HttpResponse::Ok().json(FullContentResponse {
+        content: &content,
+    }))
+}
@@ -1,11 +1,22 @@
 use actix_web::{dev::HttpServiceFactory, web, HttpResponse};
 use std::convert::TryFrom;
 
+use crate::db::models::NewItemLabel;
+use crate::events::Event;
 use crate::prelude::*;
-use stream::{ItemId, StreamId};
+use stream::{ItemId, StreamId, STATE_ID_PREFIX};
 
+mod changes;
+mod enclosure;
+mod events;
+mod favicon;
+mod item;
+mod operation;
+mod rule;
+mod saved_search;
 mod stream;
 mod subscription;
+mod tag;
 mod user_info;
 mod utils;
 
@@ -13,11 +24,27 @@ pub fn service() -> impl HttpServiceFactory {
     web::scope("/reader/api/0")
         .wrap(utils::RequireAuth)
         .service(stream::service())
+        .service(stream::search_service())
+        .service(stream::unread_count_service())
+        .service(stream::mark_all_as_read_service())
+        .service(changes::service())
+        .service(enclosure::service())
+        .service(events::service())
+        .service(item::service())
+        .service(operation::service())
+        .service(rule::service())
+        .service(saved_search::service())
         .service(subscription::service())
+        .service(tag::service())
         .service(user_info::service())
         .route("/edit-tag", web::post().to(edit_tag))
 }
 
+/// Favicons, served without authentication: see `favicon`.
+pub fn favicon_service() -> impl HttpServiceFactory {
+    favicon::service()
+}
+
 async fn edit_tag(
     data: web::Data<AppData>,
     form: web::Form<Vec<(String, String)>>,
@@ -26,6 +53,9 @@ async fn edit_tag(
 
     let mut new_is_read = None;
     let mut new_is_starred = None;
+    let mut new_is_kept_unread = None;
+    let mut added_labels = Vec::new();
+    let mut removed_labels = Vec::new();
 
     // Manually parse form because i can be repeated
     for (k, v) in form.into_inner() {
@@ -37,13 +67,26 @@ async fn edit_tag(
                 item_ids.push(id);
             }
             "a" | "r" => {
-                let id = StreamId::try_from(v.as_str()).map_err(|e| {
-                    HttpResponse::BadRequest().body(format!("Invalid stream id {}: {}", v, e))
-                })?;
+                let id = match StreamId::try_from(v.as_str()) {
+                    Ok(id) => id,
+                    // Clients may send states freader doesn't support
+                    Err(_) if v.starts_with(STATE_ID_PREFIX) => {
+                        log::debug!("Ignoring unknown state {}", v);
+                        continue;
+                    }
+                    Err(e) => {
+                        return Err(HttpResponse::BadRequest()
+                            .body(format!("Invalid stream id {}: {}", v, e))
+                            .into())
+                    }
+                };
                 match id {
                     StreamId::Read => new_is_read = Some(k == "a"),
                     StreamId::Unread => new_is_read = Some(k != "a"),
                     StreamId::Starred => new_is_starred = Some(k == "a"),
+                    StreamId::KeptUnread => new_is_kept_unread = Some(k == "a"),
+                    StreamId::UserLabel(label) if k == "a" => added_labels.push(label.0),
+                    StreamId::UserLabel(label) => removed_labels.push(label.0),
                     _ => (),
                 }
             }
@@ -51,17 +94,44 @@ async fn edit_tag(
         }
     }
 
-    if new_is_read.is_some() || new_is_starred.is_some() {
-        let mut db = data.db.clone();
+    let mut db = data.db.clone();
+    let mut is_changed = false;
+
+    if !added_labels.is_empty() || !removed_labels.is_empty() {
+        let labels = |names: &[String]| -> Vec<NewItemLabel> {
+            item_ids
+                .iter()
+                .flat_map(|item_id| {
+                    names.iter().map(move |name| NewItemLabel {
+                        item_id: item_id.0,
+                        name: name.clone(),
+                    })
+                })
+                .collect()
+        };
 
-        for item_id in item_ids {
-            let mut item = db.get_item(item_id.0).await?;
+        db.add_item_labels(labels(&added_labels)).await?;
+        db.remove_item_labels(labels(&removed_labels)).await?;
+        is_changed = true;
+    }
 
-            item.is_read = new_is_read.unwrap_or(item.is_read);
-            item.is_starred = new_is_starred.unwrap_or(item.is_starred);
+    let ids: Vec<_> = item_ids.iter().map(|id| id.0).collect();
 
-            db.update_item(item).await?;
-        }
+    // Kept unread items can't be read, which takes precedence
+    if new_is_read.is_some() || new_is_starred.is_some() || new_is_kept_unread.is_some() {
+        db.record_items_state(
+            "edit-tag",
+            ids.clone(),
+            new_is_read,
+            new_is_starred,
+            new_is_kept_unread,
+        )
+        .await?;
+        is_changed = true;
+    }
+
+    if is_changed && !ids.is_empty() {
+        data.events.send(Event::ItemsChanged(ids));
     }
 
     Ok(HttpResponse::Ok().body("OK"))
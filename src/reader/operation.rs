@@ -0,0 +1,85 @@
+use actix_web::dev::HttpServiceFactory;
+use actix_web::{web, HttpResponse};
+use serde::{Deserialize, Serialize};
+
+use crate::db::models::Operation;
+use crate::events::Event;
+use crate::prelude::*;
+
+pub fn service() -> impl HttpServiceFactory {
+    web::scope("/operation")
+        .route("/list", web::get().to(list))
+        .route("/revert", web::post().to(revert))
+}
+
+
+#[derive(Debug, Serialize)]
+struct ListResponse<'a> {
+    operations: &'a Vec<ListResponseItem<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+struct ListResponseItem<'a> {
+    id: db::Id,
+    kind: &'a str,
+    timestamp: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    read: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    starred: Option<bool>,
+    #[serde(rename = "keptUnread", skip_serializing_if = "Option::is_none")]
+    kept_unread: Option<bool>,
+    #[serde(rename = "itemCount")]
+    item_count: i32,
+    reverted: bool,
+}
+
+/// List the operations which can still be reverted, newest first.
+async fn list(data: web::Data<AppData>) -> actix_web::Result<HttpResponse> {
+    let min_date = Operation::min_date(data.cfg.undo_window);
+    let operations = data.db.clone().get_operations(min_date).await?;
+
+    let operations = operations
+        .iter()
+        .map(|operation| ListResponseItem {
+            id: operation.id,
+            kind: &operation.kind,
+            timestamp: operation.created_at.timestamp(),
+            read: operation.is_read,
+            starred: operation.is_starred,
+            kept_unread: operation.is_kept_unread,
+            item_count: operation.item_count,
+            reverted: operation.is_reverted,
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok().json(ListResponse {
+        operations: &operations,
+    }))
+}
+
+
+#[derive(Debug, Deserialize)]
+struct RevertData {
+    id: db::Id,
+}
+
+/// Restore the state items had before an operation.
+async fn revert(
+    data: web::Data<AppData>,
+    form: web::Form<RevertData>,
+) -> actix_web::Result<HttpResponse> {
+    let min_date = Operation::min_date(data.cfg.undo_window);
+
+    match data.db.clone().revert_operation(form.id, min_date).await? {
+        Some(ids) => {
+            let count = ids.len();
+            if !ids.is_empty() {
+                data.events.send(Event::ItemsChanged(ids));
+            }
+
+            Ok(HttpResponse::Ok().body(count.to_string()))
+        }
+        None => Ok(HttpResponse::NotFound().body("Unknown, expired or reverted operation")),
+    }
+}
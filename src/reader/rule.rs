@@ -0,0 +1,143 @@
+use actix_web::dev::HttpServiceFactory;
+use actix_web::{web, HttpResponse};
+use serde::{Deserialize, Serialize};
+
+use super::stream::StreamId;
+use super::subscription::{LabelId, SubscriptionId};
+use crate::db::models::NewRule;
+use crate::events::Event;
+use crate::prelude::*;
+use crate::rules;
+
+pub fn service() -> impl HttpServiceFactory {
+    web::scope("/rule")
+        .route("/apply", web::post().to(apply))
+        .route("/edit", web::post().to(edit))
+        .route("/list", web::get().to(list))
+}
+
+
+#[derive(Debug, Serialize)]
+struct ListResponse<'a> {
+    rules: &'a Vec<ListResponseItem<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+struct ListResponseItem<'a> {
+    name: &'a str,
+    field: &'a str,
+    pattern: &'a str,
+    regex: bool,
+    #[serde(rename = "s", skip_serializing_if = "Option::is_none")]
+    scope: Option<StreamId>,
+    action: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    label: &'a Option<String>,
+}
+
+async fn list(data: web::Data<AppData>) -> actix_web::Result<HttpResponse> {
+    let rules = data.db.clone().get_rules().await?;
+
+    let rules = rules
+        .iter()
+        .map(|rule| ListResponseItem {
+            name: &rule.name,
+            field: &rule.field,
+            pattern: &rule.pattern,
+            regex: rule.is_regex,
+            scope: match (rule.subscription_id, &rule.category) {
+                (Some(id), _) => Some(StreamId::Subscription(SubscriptionId(id))),
+                (None, Some(name)) => Some(StreamId::UserLabel(LabelId(name.clone()))),
+                (None, None) => None,
+            },
+            action: &rule.action,
+            label: &rule.label,
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok().json(ListResponse {
+        rules: &rules,
+    }))
+}
+
+
+#[derive(Debug, Deserialize)]
+struct EditData {
+    #[serde(rename = "ac")]
+    op: String,
+    name: String,
+    /// Item field: title, content, author, url or category.
+    field: Option<String>,
+    pattern: Option<String>,
+    /// Match `pattern` as a regex instead of a keyword.
+    #[serde(default)]
+    regex: bool,
+    /// Feed or label the rule is restricted to.
+    #[serde(rename = "s")]
+    scope: Option<StreamId>,
+    /// Rule action: read, star, label or drop.
+    action: Option<String>,
+    label: Option<String>,
+}
+
+async fn edit(
+    data: web::Data<AppData>,
+    form: web::Form<EditData>,
+) -> actix_web::Result<HttpResponse> {
+    let mut db = data.db.clone();
+    let form = form.into_inner();
+
+    match form.op.as_str() {
+        "add" => {
+            let (field, pattern, action) = match (form.field, form.pattern, form.action) {
+                (Some(field), Some(pattern), Some(action)) => (field, pattern, action),
+                _ => return Ok(HttpResponse::BadRequest().body("Missing rule values")),
+            };
+
+            let (subscription_id, category) = match form.scope {
+                None => (None, None),
+                Some(StreamId::Subscription(id)) => (Some(id.0), None),
+                Some(StreamId::UserLabel(label)) => (None, Some(label.0)),
+                Some(_) => return Ok(HttpResponse::BadRequest().body("Bad value for s")),
+            };
+
+            let new_rule = NewRule {
+                name: form.name,
+                field,
+                pattern,
+                is_regex: form.regex,
+                subscription_id,
+                category,
+                action,
+                label: form.label,
+            };
+
+            if let Err(e) = rules::validate(&new_rule) {
+                return Ok(HttpResponse::BadRequest().body(e));
+            }
+
+            db.create_rule(new_rule).await?;
+        }
+        "remove" => {
+            if !db.remove_rule(form.name).await? {
+                return Ok(HttpResponse::NotFound().body("Unknown rule"));
+            }
+        }
+        _ => return Ok(HttpResponse::BadRequest().body("Bad value for ac")),
+    }
+
+    Ok(HttpResponse::Ok().body("OK"))
+}
+
+
+/// Apply the rules to stored items.
+async fn apply(data: web::Data<AppData>) -> actix_web::Result<HttpResponse> {
+    let ids = rules::reapply(&mut data.db.clone()).await?;
+    let count = ids.len();
+
+    if !ids.is_empty() {
+        data.events.send(Event::ItemsChanged(ids));
+    }
+
+    Ok(HttpResponse::Ok().body(count.to_string()))
+}
@@ -0,0 +1,98 @@
+use actix_web::dev::HttpServiceFactory;
+use actix_web::{web, HttpResponse};
+use serde::{Deserialize, Serialize};
+
+use crate::db::models::NewSavedSearch;
+use crate::prelude::*;
+
+pub fn service() -> impl HttpServiceFactory {
+    web::scope("/saved-search").route("/edit", web::post().to(edit))
+}
+
+
+#[derive(Debug, Deserialize)]
+struct EditData {
+    #[serde(rename = "s")]
+    id: SavedSearchId,
+    #[serde(rename = "ac")]
+    action: String,
+    /// Full-text search query.
+    #[serde(rename = "q")]
+    query: Option<String>,
+}
+
+async fn edit(
+    data: web::Data<AppData>,
+    form: web::Form<EditData>,
+) -> actix_web::Result<HttpResponse> {
+    let mut db = data.db.clone();
+    let EditData { id, action, query } = form.into_inner();
+
+    match action.as_str() {
+        "add" => {
+            let query = match query {
+                Some(query) if !query.trim().is_empty() => query,
+                _ => return Ok(HttpResponse::BadRequest().body("Missing value for q")),
+            };
+
+            // Make sure the query is valid
+            let filter = db::ItemFilter {
+                search: Some(query.clone()),
+                ..Default::default()
+            };
+            if db.count_items(filter).await.is_err() {
+                return Ok(HttpResponse::BadRequest().body("Invalid search query"));
+            }
+
+            let new_saved_search = NewSavedSearch { name: id.0, query };
+            match db.create_saved_search(new_saved_search).await {
+                Ok(_) => (),
+                Err(e) if e.is_unique_violation() => {
+                    return Ok(HttpResponse::Conflict().body("Saved search already exists"));
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+        "remove" => {
+            db.remove_saved_search(id.0).await?;
+        }
+        _ => return Ok(HttpResponse::BadRequest().body("Bad value for ac")),
+    }
+
+    Ok(HttpResponse::Ok().body("OK"))
+}
+
+
+pub const SAVED_SEARCH_ID_PREFIX: &str = "user/-/search/";
+
+/// A saved search is a full-text search query used as a stream.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Hash, Eq, PartialEq)]
+#[serde(into = "String", try_from = "String")]
+pub struct SavedSearchId(pub String);
+
+impl std::convert::Into<String> for SavedSearchId {
+    fn into(self) -> String {
+        format!("{}{}", SAVED_SEARCH_ID_PREFIX, self.0)
+    }
+}
+
+impl std::convert::TryFrom<String> for SavedSearchId {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Self::try_from(value.as_str())
+    }
+}
+
+impl<'a> std::convert::TryFrom<&'a str> for SavedSearchId {
+    type Error = String;
+
+    fn try_from(value: &'a str) -> Result<Self, Self::Error> {
+        if value.starts_with(SAVED_SEARCH_ID_PREFIX) && value.len() > SAVED_SEARCH_ID_PREFIX.len() {
+            Ok(Self(value[SAVED_SEARCH_ID_PREFIX.len()..].to_owned()))
+        } else {
+            Err(format!("Invalid saved search ID: {}", value))
+        }
+    }
+}
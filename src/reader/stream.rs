@@ -1,9 +1,17 @@
 use actix_web::dev::HttpServiceFactory;
 use actix_web::{web, HttpResponse};
 use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::HashMap;
 use std::convert::TryFrom;
 
+use super::enclosure;
+use super::saved_search::{SavedSearchId, SAVED_SEARCH_ID_PREFIX};
 use super::subscription::{LabelId, SubscriptionId, LABEL_ID_PREFIX, SUBSCRIPTION_ID_PREFIX};
+use super::tag::{TagId, TAG_ID_PREFIX};
+use crate::db::models::Item;
+use crate::events::Event;
+use crate::html;
 use crate::prelude::*;
 
 pub fn service() -> impl HttpServiceFactory {
@@ -12,6 +20,18 @@ pub fn service() -> impl HttpServiceFactory {
         .route("/items/ids", web::get().to(item_ids))
 }
 
+pub fn search_service() -> impl HttpServiceFactory {
+    web::resource("/search/items/ids").route(web::get().to(search_item_ids))
+}
+
+pub fn unread_count_service() -> impl HttpServiceFactory {
+    web::resource("/unread-count").route(web::get().to(unread_count))
+}
+
+pub fn mark_all_as_read_service() -> impl HttpServiceFactory {
+    web::resource("/mark-all-as-read").route(web::post().to(mark_all_as_read))
+}
+
 #[derive(Debug, Deserialize)]
 struct ItemIdsQuery {
     #[serde(rename = "s")]
@@ -24,6 +44,9 @@ struct ItemIdsQuery {
     max_date: Option<chrono::DateTime<chrono::Utc>>,
     #[serde(rename = "n")]
     count: usize,
+    /// Full-text search query.
+    #[serde(rename = "q")]
+    search: Option<String>,
 }
 
 #[derive(Debug, Serialize)]
@@ -46,32 +69,24 @@ async fn item_ids(
     data: web::Data<AppData>,
     query: web::Query<ItemIdsQuery>,
 ) -> actix_web::Result<HttpResponse> {
-    use StreamId::{Read, Starred};
-
     let mut db = data.db.clone();
 
     if matches!(&query.exclude, Some(excluded) if excluded == &query.stream) {
         return Ok(HttpResponse::BadRequest().body("Same value for s and xt"));
     }
 
-    let is_read = match (&query.stream, &query.exclude) {
-        (Read, _) => Some(true),
-        (_, Some(Read)) => Some(false),
-        _ => None,
-    };
-
-    let is_starred = match (&query.stream, &query.exclude) {
-        (Starred, _) => Some(true),
-        (_, Some(Starred)) => Some(false),
-        _ => None,
-    };
-
-    let min_date = query.min_date.map(|dt| dt.naive_utc());
-    let max_date = query.max_date.map(|dt| dt.naive_utc());
+    let mut filter = item_filter(&mut db, &query.stream, query.exclude.as_ref()).await?;
+    if let Some(search) = query.search.clone().filter(|q| !q.is_empty()) {
+        filter.add_search(search);
+    }
+    filter.min_date = query.min_date.map(|dt| dt.naive_utc());
+    filter.max_date = query.max_date.map(|dt| dt.naive_utc());
 
+    let has_search = filter.search.is_some();
     let items = db
-        .find_items(is_read, is_starred, min_date, max_date, query.count)
-        .await?;
+        .find_items(filter, query.count)
+        .await
+        .map_err(|e| search_error(e, has_search))?;
     let item_refs = items
         .into_iter()
         .map(|item| ItemIdsResponseItem {
@@ -88,6 +103,128 @@ async fn item_ids(
     }))
 }
 
+/// Get the filter matching the items of `stream`, minus the ones of `exclude`.
+pub async fn item_filter(
+    db: &mut db::Helper,
+    stream: &StreamId,
+    exclude: Option<&StreamId>,
+) -> actix_web::Result<db::ItemFilter> {
+    use StreamId::{Read, RecentlyRead, Starred};
+
+    let mut filter = db::ItemFilter {
+        read: match (stream, exclude) {
+            (Read, _) | (RecentlyRead, _) => Some(true),
+            (_, Some(Read)) => Some(false),
+            _ => None,
+        },
+        starred: match (stream, exclude) {
+            (Starred, _) => Some(true),
+            (_, Some(Starred)) => Some(false),
+            _ => None,
+        },
+        order: match stream {
+            RecentlyRead => db::ItemOrder::Read,
+            Starred => db::ItemOrder::Starred,
+            _ => db::ItemOrder::Published,
+        },
+        ..Default::default()
+    };
+
+    match stream {
+        StreamId::KeptUnread | StreamId::TrackingKeptUnread => filter.kept_unread = Some(true),
+        StreamId::Subscription(id) => filter.subscription = Some(id.0),
+        StreamId::UserLabel(label) => filter.category = Some(label.0.clone()),
+        StreamId::Tag(tag) => filter.tag = Some(tag.0.clone()),
+        StreamId::SavedSearch(id) => {
+            let saved_search = db
+                .get_saved_search(id.0.clone())
+                .await?
+                .ok_or_else(|| HttpResponse::NotFound().body("Unknown saved search"))?;
+
+            filter.search = Some(saved_search.query);
+        }
+        _ => (),
+    }
+
+    Ok(filter)
+}
+
+/// Report invalid search queries as bad requests.
+///
+/// SQLite doesn't tell query errors apart, so errors of queries without a
+/// search, or caused by locks, are reported as is.
+fn search_error(e: db::Error, has_search: bool) -> actix_web::Error {
+    match e {
+        db::Error::DatabaseError(diesel::result::Error::DatabaseError(_, info))
+            if has_search && !info.message().contains("locked") =>
+        {
+            log::debug!("Invalid search query: {}", info.message());
+            HttpResponse::BadRequest()
+                .body("Invalid search query")
+                .into()
+        }
+        e => e.into(),
+    }
+}
+
+
+#[derive(Debug, Deserialize)]
+struct SearchQuery {
+    #[serde(rename = "q")]
+    search: String,
+    #[serde(rename = "n", default = "SearchQuery::default_count")]
+    count: usize,
+    /// Restrict the search to a stream.
+    #[serde(rename = "s")]
+    stream: Option<StreamId>,
+}
+
+impl SearchQuery {
+    fn default_count() -> usize {
+        1000
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct SearchResponse<'a> {
+    results: &'a Vec<SearchResponseItem>,
+}
+
+#[derive(Debug, Serialize)]
+struct SearchResponseItem {
+    #[serde(serialize_with = "item_id::short")]
+    id: ItemId,
+}
+
+async fn search_item_ids(
+    data: web::Data<AppData>,
+    query: web::Query<SearchQuery>,
+) -> actix_web::Result<HttpResponse> {
+    let mut db = data.db.clone();
+
+    let mut filter = match &query.stream {
+        Some(stream) => item_filter(&mut db, stream, None).await?,
+        None => db::ItemFilter::default(),
+    };
+    filter.add_search(query.search.clone());
+
+    let items = db
+        .find_items(filter, query.count)
+        .await
+        .map_err(|e| search_error(e, true))?;
+    let results = items
+        .into_iter()
+        .map(|item| SearchResponseItem {
+            id: ItemId(item.id),
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok().json(SearchResponse {
+        results: &results,
+    }))
+}
+
+
 // serde_urlencoded doesn't support repeated items because it is non-standard.
 // We must manually parse the key/value pairs.
 type ItemContentsForm = Vec<(String, String)>;
@@ -111,6 +248,13 @@ struct ItemContentsResponseItem<'a> {
     link: Vec<ItemContentsResponseItemLink<'a>>,
     origin: ItemContentsResponseItemOrigin<'a>,
     summary: ItemContentsResponseItemSummary<'a>,
+    /// Full article, when it was fetched.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content: Option<ItemContentsResponseItemSummary<'a>>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    enclosure: Vec<ItemContentsResponseItemEnclosure<'a>>,
+    /// States, labels and feed tags of the item.
+    categories: Vec<StreamId>,
 }
 
 #[derive(Debug, Serialize)]
@@ -132,6 +276,21 @@ struct ItemContentsResponseItemSummary<'a> {
     content: &'a str,
 }
 
+#[derive(Debug, Serialize)]
+struct ItemContentsResponseItemEnclosure<'a> {
+    /// Points to freader when the enclosure was downloaded.
+    href: Cow<'a, str>,
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    mime_type: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    length: Option<String>,
+    /// In seconds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    duration: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    thumbnail: Option<Cow<'a, str>>,
+}
+
 async fn item_contents(
     data: web::Data<AppData>,
     form: web::Form<ItemContentsForm>,
@@ -152,11 +311,46 @@ async fn item_contents(
         ids
     };
 
-    let pairs = db.get_items_and_subscriptions(ids).await?;
+    let pairs = db.get_items_and_subscriptions(ids.clone()).await?;
+
+    let items: Vec<&Item> = pairs.iter().map(|(item, _)| item).collect();
+    let mut categories = items_categories(&mut db, &items).await?;
+
+    let mut enclosures: HashMap<_, Vec<_>> = HashMap::new();
+    for enclosure in db.get_items_enclosures(ids).await? {
+        enclosures
+            .entry(enclosure.item_id)
+            .or_default()
+            .push(enclosure);
+    }
+
+    let image_proxy = &data.image_proxy;
+    let proxied = |content: &'_ str| -> String {
+        html::rewrite_image_urls(content, |url| image_proxy.proxy_url(url))
+    };
+    let contents: Vec<(Cow<str>, Option<Cow<str>>)> = pairs
+        .iter()
+        .map(|(item, _)| {
+            let full_content = item.full_content.as_deref().filter(|c| !c.is_empty());
+
+            if data.cfg.image_proxy {
+                (
+                    Cow::Owned(proxied(&item.content)),
+                    full_content.map(|c| Cow::Owned(proxied(c))),
+                )
+            } else {
+                (
+                    Cow::Borrowed(item.content.as_str()),
+                    full_content.map(Cow::Borrowed),
+                )
+            }
+        })
+        .collect();
 
     let items = pairs
         .iter()
-        .map(|(item, subscription)| ItemContentsResponseItem {
+        .zip(&contents)
+        .map(|((item, subscription), content)| ItemContentsResponseItem {
             id: ItemId(item.id),
             title: &item.title,
             author: item.author.as_deref().unwrap_or(""),
@@ -174,8 +368,39 @@ async fn item_contents(
                 site_url: &subscription.site_url,
             },
             summary: ItemContentsResponseItemSummary {
-                content: &item.content,
+                content: content.0.as_ref(),
             },
+            content: content
+                .1
+                .as_deref()
+                .map(|content| ItemContentsResponseItemSummary { content }),
+            enclosure: enclosures
+                .get(&item.id)
+                .map(|enclosures| {
+                    enclosures
+                        .iter()
+                        .map(|enclosure| ItemContentsResponseItemEnclosure {
+                            href: if enclosure.is_downloaded() {
+                                Cow::Owned(enclosure::url(&data.cfg, enclosure.id))
+                            } else {
+                                Cow::Borrowed(&enclosure.url)
+                            },
+                            mime_type: enclosure.mime_type.as_deref(),
+                            length: enclosure.length.map(|length| length.to_string()),
+                            duration: enclosure.duration,
+                            thumbnail: enclosure.thumbnail_url.as_deref().map(|url| {
+                                let proxied = if data.cfg.image_proxy {
+                                    image_proxy.proxy_url(url)
+                                } else {
+                                    None
+                                };
+                                proxied.map_or(Cow::Borrowed(url), Cow::Owned)
+                            }),
+                        })
+                        .collect()
+                })
+                .unwrap_or_default(),
+            categories: categories.remove(&item.id).unwrap_or_default(),
         })
         .collect();
 
@@ -184,6 +409,189 @@ async fn item_contents(
     }))
 }
 
+/// Get the states, labels and feed tags of items, by item.
+pub async fn items_categories(
+    db: &mut db::Helper,
+    items: &[&Item],
+) -> actix_web::Result<HashMap<db::Id, Vec<StreamId>>> {
+    let mut categories: HashMap<_, Vec<_>> = items
+        .iter()
+        .map(|item| {
+            let mut ids = vec![StreamId::Unread];
+            if item.is_read {
+                ids.push(StreamId::Read);
+            }
+            if item.is_starred {
+                ids.push(StreamId::Starred);
+            }
+            if item.is_kept_unread {
+                ids.push(StreamId::KeptUnread);
+            }
+            (item.id, ids)
+        })
+        .collect();
+
+    let ids: Vec<db::Id> = items.iter().map(|item| item.id).collect();
+
+    for label in db.get_items_labels(ids.clone()).await? {
+        if let Some(item_categories) = categories.get_mut(&label.item_id) {
+            item_categories.push(StreamId::UserLabel(LabelId(label.name)));
+        }
+    }
+    for tag in db.get_items_tags(ids).await? {
+        if let Some(item_categories) = categories.get_mut(&tag.item_id) {
+            item_categories.push(StreamId::Tag(TagId(tag.name)));
+        }
+    }
+
+    Ok(categories)
+}
+
+
+#[derive(Debug, Serialize)]
+struct UnreadCountResponse<'a> {
+    max: usize,
+    #[serde(rename = "unreadcounts")]
+    unread_counts: &'a Vec<UnreadCountResponseItem>,
+}
+
+#[derive(Debug, Serialize)]
+struct UnreadCountResponseItem {
+    id: StreamId,
+    count: i64,
+    #[serde(rename = "newestItemTimestampUsec")]
+    newest_item_timestamp_usec: String,
+}
+
+impl UnreadCountResponseItem {
+    fn new(id: StreamId, count: i64, newest: Option<chrono::NaiveDateTime>) -> Self {
+        Self {
+            id,
+            count,
+            newest_item_timestamp_usec: newest
+                .map(|dt| dt.timestamp() * 1_000_000 + dt.timestamp_subsec_micros() as i64)
+                .unwrap_or(0)
+                .to_string(),
+        }
+    }
+}
+
+async fn unread_count(data: web::Data<AppData>) -> actix_web::Result<HttpResponse> {
+    let mut db = data.db.clone();
+
+    let counts = db.count_unread_items().await?;
+    let subscription_counts: HashMap<_, _> = counts
+        .iter()
+        .map(|(id, count, newest)| (*id, (*count, *newest)))
+        .collect();
+
+    let mut unread_counts = Vec::with_capacity(counts.len() + 1);
+
+    let total = counts.iter().map(|(_, count, _)| count).sum();
+    let newest = counts.iter().filter_map(|(_, _, newest)| *newest).max();
+    unread_counts.push(UnreadCountResponseItem::new(
+        StreamId::Unread,
+        total,
+        newest,
+    ));
+
+    // Sum the counts of the subscriptions in each label
+    let mut label_counts: Vec<(String, i64, Option<chrono::NaiveDateTime>)> = Vec::new();
+    for (category, subscription_id) in db.get_categories_and_subscription_ids().await? {
+        let (count, newest) = match subscription_counts.get(&subscription_id) {
+            Some(counts) => *counts,
+            None => continue,
+        };
+
+        match label_counts.last_mut() {
+            Some(last) if last.0 == category.name => {
+                last.1 += count;
+                last.2 = last.2.max(newest);
+            }
+            _ => label_counts.push((category.name, count, newest)),
+        }
+    }
+    for (name, count, newest) in label_counts {
+        unread_counts.push(UnreadCountResponseItem::new(
+            StreamId::UserLabel(LabelId(name)),
+            count,
+            newest,
+        ));
+    }
+
+    for (id, count, newest) in counts {
+        unread_counts.push(UnreadCountResponseItem::new(
+            StreamId::Subscription(SubscriptionId(id)),
+            count,
+            newest,
+        ));
+    }
+
+    for saved_search in db.get_saved_searches().await? {
+        let filter = db::ItemFilter {
+            read: Some(false),
+            search: Some(saved_search.query),
+            ..Default::default()
+        };
+
+        let count = db.count_items(filter.clone()).await?;
+        if count == 0 {
+            continue;
+        }
+        let newest = db.find_items(filter, 1).await?;
+
+        unread_counts.push(UnreadCountResponseItem::new(
+            StreamId::SavedSearch(SavedSearchId(saved_search.name)),
+            count as i64,
+            newest.first().map(|item| item.published),
+        ));
+    }
+
+    Ok(HttpResponse::Ok().json(UnreadCountResponse {
+        max: unread_counts.len(),
+        unread_counts: &unread_counts,
+    }))
+}
+
+
+#[derive(Debug, Deserialize)]
+struct MarkAllAsReadData {
+    #[serde(rename = "s")]
+    stream: StreamId,
+    /// Only mark items older than this timestamp, in microseconds.
+    #[serde(rename = "ts")]
+    timestamp_usec: Option<i64>,
+}
+
+async fn mark_all_as_read(
+    data: web::Data<AppData>,
+    form: web::Form<MarkAllAsReadData>,
+) -> actix_web::Result<HttpResponse> {
+    let mut db = data.db.clone();
+
+    let mut filter = item_filter(&mut db, &form.stream, None).await?;
+    filter.read = Some(false);
+    if let Some(ts) = form.timestamp_usec {
+        filter.max_date = Some(chrono::NaiveDateTime::from_timestamp(
+            ts.div_euclid(1_000_000),
+            (ts.rem_euclid(1_000_000) * 1_000) as u32,
+        ));
+    }
+
+    let ids = db.find_item_ids(filter, None).await?;
+    db.record_items_state("mark-all-as-read", ids.clone(), Some(true), None, None)
+        .await?;
+
+    if !ids.is_empty() {
+        data.events.send(Event::ItemsChanged(ids));
+    }
+
+    Ok(HttpResponse::Ok().body("OK"))
+}
+
+
+/// Prefix of the states defined by Google Reader.
+pub const STATE_ID_PREFIX: &str = "user/-/state/com.google/";
 
 /// A Stream represents a set of items.
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -196,10 +604,21 @@ pub enum StreamId {
     Read,
     /// All starred items.
     Starred,
+    /// All items the user explicitly kept unread.
+    KeptUnread,
+    /// Read items, the most recently read first.
+    RecentlyRead,
+    /// Items that were kept unread at some point, freader only knows the
+    /// ones still kept unread.
+    TrackingKeptUnread,
     /// All items with a tag/in a folder.
     UserLabel(LabelId),
     /// All items from a subscription.
     Subscription(SubscriptionId),
+    /// All items matching a saved search.
+    SavedSearch(SavedSearchId),
+    /// All items with a tag from their feed.
+    Tag(TagId),
 }
 
 impl std::convert::Into<String> for StreamId {
@@ -210,8 +629,13 @@ impl std::convert::Into<String> for StreamId {
             Unread => "user/-/state/com.google/reading-list".to_owned(),
             Read => "user/-/state/com.google/read".to_owned(),
             Starred => "user/-/state/com.google/starred".to_owned(),
+            KeptUnread => "user/-/state/com.google/kept-unread".to_owned(),
+            RecentlyRead => "user/-/state/freader/recently-read".to_owned(),
+            TrackingKeptUnread => "user/-/state/com.google/tracking-kept-unread".to_owned(),
             UserLabel(id) => id.into(),
             Subscription(id) => id.into(),
+            SavedSearch(id) => id.into(),
+            Tag(id) => id.into(),
         }
     }
 }
@@ -234,10 +658,17 @@ impl<'a> TryFrom<&'a str> for StreamId {
             "user/-/state/com.google/reading-list" => Unread,
             "user/-/state/com.google/read" => Read,
             "user/-/state/com.google/starred" => Starred,
+            "user/-/state/com.google/kept-unread" => KeptUnread,
+            "user/-/state/freader/recently-read" => RecentlyRead,
+            "user/-/state/com.google/tracking-kept-unread" => TrackingKeptUnread,
             s if s.starts_with(LABEL_ID_PREFIX) => UserLabel(LabelId::try_from(value)?),
             s if s.starts_with(SUBSCRIPTION_ID_PREFIX) => {
                 Subscription(SubscriptionId::try_from(value)?)
             }
+            s if s.starts_with(SAVED_SEARCH_ID_PREFIX) => {
+                SavedSearch(SavedSearchId::try_from(value)?)
+            }
+            s if s.starts_with(TAG_ID_PREFIX) => Tag(TagId::try_from(value)?),
             _ => return Err(format!("Invalid stream ID: {}", value)),
         })
     }
@@ -287,7 +718,7 @@ impl<'a> TryFrom<&'a str> for ItemId {
 }
 
 /// Specialized serialization for item IDs.
-mod item_id {
+pub(super) mod item_id {
     use serde::Serializer;
 
     use super::ItemId;
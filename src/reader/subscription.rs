@@ -1,8 +1,17 @@
 use actix_web::dev::HttpServiceFactory;
 use actix_web::{web, HttpResponse};
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt;
 
+use super::favicon;
+use super::saved_search::SavedSearchId;
+use super::stream::StreamId;
+use crate::credentials::{Auth, Credentials};
 use crate::db::models::Category;
+use crate::events::Event;
+use crate::feed_manager::{FeedManager, FetchOptions};
+use crate::html::{self, FeedLink};
 use crate::prelude::*;
 
 pub fn service() -> impl HttpServiceFactory {
@@ -20,10 +29,12 @@ struct ListResponse<'a> {
 
 #[derive(Debug, Serialize)]
 struct ListResponseItem<'a> {
-    id: SubscriptionId,
+    id: StreamId,
     title: &'a str,
     #[serde(rename = "htmlUrl", skip_serializing_if = "Option::is_none")]
     site_url: &'a Option<String>,
+    #[serde(rename = "iconUrl", skip_serializing_if = "Option::is_none")]
+    icon_url: Option<String>,
     categories: Vec<ListResponseCategoryItem<'a>>,
 }
 
@@ -37,6 +48,12 @@ async fn list(data: web::Data<AppData>) -> actix_web::Result<HttpResponse> {
     let mut db = data.db.clone();
 
     let subscriptions = db.get_subscriptions().await?;
+    let saved_searches = db.get_saved_searches().await?;
+    let favicon_ids: HashSet<db::Id> = db
+        .get_favicon_subscription_ids()
+        .await?
+        .into_iter()
+        .collect();
 
     let mut categories: Vec<Vec<Category>> = Vec::with_capacity(subscriptions.len());
     for subscription in &subscriptions {
@@ -53,12 +70,25 @@ async fn list(data: web::Data<AppData>) -> actix_web::Result<HttpResponse> {
             });
 
             ListResponseItem {
-                id: SubscriptionId(subscription.id),
+                id: StreamId::Subscription(SubscriptionId(subscription.id)),
                 title: &subscription.title,
                 site_url: &subscription.site_url,
+                icon_url: if favicon_ids.contains(&subscription.id) {
+                    Some(favicon::url(&data, subscription.id))
+                } else {
+                    None
+                },
                 categories: categories.collect(),
             }
         })
+        // Saved searches are listed as virtual feeds
+        .chain(saved_searches.iter().map(|saved_search| ListResponseItem {
+            id: StreamId::SavedSearch(SavedSearchId(saved_search.name.clone())),
+            title: &saved_search.name,
+            site_url: &None,
+            icon_url: None,
+            categories: Vec::new(),
+        }))
         .collect();
 
     Ok(HttpResponse::Ok().json(ListResponse {
@@ -71,6 +101,7 @@ async fn list(data: web::Data<AppData>) -> actix_web::Result<HttpResponse> {
 struct QuickAddQuery {
     #[serde(rename = "quickadd")]
     url: String,
+    proxy: Option<String>,
 }
 
 #[derive(Debug, Serialize)]
@@ -79,7 +110,10 @@ struct QuickAddResponse<'a> {
     stream_id: SubscriptionId,
     query: &'a str,
     #[serde(rename = "numResults")]
-    num_results: u8,
+    num_results: usize,
+    /// Feeds found on the web page, the first one is subscribed to.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    candidates: Option<&'a [FeedLink]>,
 }
 
 #[derive(Debug, Serialize)]
@@ -93,19 +127,50 @@ struct QuickAddErrorResponse<'a> {
 async fn quickadd(
     data: web::Data<AppData>,
     query: web::Query<QuickAddQuery>,
+    form: Option<web::Form<CredentialsForm>>,
 ) -> actix_web::Result<HttpResponse> {
-    let subscription = data.feed_manager.subscribe(&query.url).await.map_err(|e| {
+    let error_response = |e: &str| {
         HttpResponse::Ok().json(QuickAddErrorResponse {
             query: &query.url,
             num_results: 0,
             error: e,
         })
-    })?;
+    };
+
+    if let Some(proxy) = &query.proxy {
+        FeedManager::check_proxy(proxy).map_err(error_response)?;
+    }
+
+    let credentials = match form {
+        Some(form) if form.is_set() => Some(
+            form.into_inner()
+                .into_credentials()
+                .map_err(error_response)?,
+        ),
+        _ => None,
+    };
+
+    let options = FetchOptions {
+        proxy: query.proxy.clone(),
+        credentials,
+    };
+
+    let subscribed = data
+        .feed_manager
+        .subscribe(&query.url, options)
+        .await
+        .map_err(error_response)?;
+
+    let subscription = &subscribed.subscription;
+
+    // Only list candidates when there was a choice
+    let candidates = Some(subscribed.candidates.as_slice()).filter(|c| c.len() > 1);
 
     Ok(HttpResponse::Ok().json(QuickAddResponse {
         query: &subscription.feed_url,
         stream_id: SubscriptionId(subscription.id),
-        num_results: 1,
+        num_results: candidates.map_or(1, <[_]>::len),
+        candidates,
     }))
 }
 
@@ -122,6 +187,22 @@ struct EditData {
     add_category: Option<LabelId>,
     #[serde(rename = "r")]
     remove_category: Option<LabelId>,
+    /// Empty to use the global proxy.
+    proxy: Option<String>,
+    /// Retention overrides, empty to inherit.
+    keep_read_days: Option<String>,
+    keep_max_items: Option<String>,
+    /// Whether to download the full article of new items.
+    fetch_full_content: Option<String>,
+    /// CSS selector of the article, empty to detect it.
+    content_selector: Option<String>,
+    /// Whether to download the enclosures of new items.
+    download_enclosures: Option<String>,
+    /// Number of most recent items whose downloads are kept, empty for all.
+    keep_downloads: Option<String>,
+    /// Replaces existing credentials if set.
+    #[serde(flatten)]
+    credentials: CredentialsForm,
 }
 
 async fn edit(
@@ -132,12 +213,121 @@ async fn edit(
 
     match form.action.as_str() {
         "edit" => {
-            if let Some(title) = form.title.take() {
-                db.transform_subscription(form.id.0, move |subscription| {
+            // Every field is checked before any is changed
+            let title = form.title.take();
+
+            let proxy = match form.proxy.take() {
+                None => None,
+                Some(proxy) if proxy.is_empty() => Some(None),
+                Some(proxy) => match FeedManager::check_proxy(&proxy) {
+                    Ok(_) => Some(Some(proxy)),
+                    Err(e) => return Ok(HttpResponse::BadRequest().body(e)),
+                },
+            };
+
+            let parse_retention = |value: Option<String>| match value.as_deref() {
+                None => Ok(None),
+                Some("") => Ok(Some(None)),
+                Some(value) => match value.parse::<i32>() {
+                    Ok(value) if value >= 0 => Ok(Some(Some(value))),
+                    _ => Err(()),
+                },
+            };
+
+            let (keep_read_days, keep_max_items) = match (
+                parse_retention(form.keep_read_days.take()),
+                parse_retention(form.keep_max_items.take()),
+            ) {
+                (Ok(read_days), Ok(max_items)) => (read_days, max_items),
+                _ => return Ok(HttpResponse::BadRequest().body("Invalid retention value")),
+            };
+
+            let fetch_full_content = match form.fetch_full_content.take() {
+                None => None,
+                Some(value) => match parse_flag(&value) {
+                    Some(flag) => Some(flag),
+                    None => {
+                        return Ok(HttpResponse::BadRequest().body("Invalid fetch_full_content"))
+                    }
+                },
+            };
+
+            let content_selector = match form.content_selector.take() {
+                None => None,
+                Some(selector) if selector.trim().is_empty() => Some(None),
+                Some(selector) => match html::check_selector(&selector) {
+                    Ok(_) => Some(Some(selector)),
+                    Err(e) => return Ok(HttpResponse::BadRequest().body(e)),
+                },
+            };
+
+            let download_enclosures = match form.download_enclosures.take() {
+                None => None,
+                Some(value) => match parse_flag(&value) {
+                    Some(flag) => Some(flag),
+                    None => {
+                        return Ok(HttpResponse::BadRequest().body("Invalid download_enclosures"))
+                    }
+                },
+            };
+
+            let keep_downloads = match form.keep_downloads.take().as_deref() {
+                None => None,
+                Some("") => Some(None),
+                Some(value) => match value.parse::<i32>() {
+                    Ok(keep) if keep > 0 => Some(Some(keep)),
+                    _ => return Ok(HttpResponse::BadRequest().body("Invalid keep_downloads")),
+                },
+            };
+
+            let credentials = if form.credentials.is_set() {
+                let credentials = match std::mem::take(&mut form.credentials).into_credentials() {
+                    Ok(credentials) => credentials,
+                    Err(e) => return Ok(HttpResponse::BadRequest().body(e)),
+                };
+
+                if credentials.is_empty() {
+                    Some(None)
+                } else {
+                    match data.feed_manager.encrypt_credentials(&credentials) {
+                        Ok(encrypted) => Some(Some(encrypted)),
+                        Err(e) => return Ok(HttpResponse::BadRequest().body(e)),
+                    }
+                }
+            } else {
+                None
+            };
+
+            db.transform_subscription(form.id.0, move |subscription| {
+                if let Some(title) = title {
                     subscription.title = title;
-                })
-                .await?;
-            }
+                }
+                if let Some(proxy) = proxy {
+                    subscription.proxy = proxy;
+                }
+                if let Some(read_days) = keep_read_days {
+                    subscription.keep_read_days = read_days;
+                }
+                if let Some(max_items) = keep_max_items {
+                    subscription.keep_max_items = max_items;
+                }
+                if let Some(fetch_full_content) = fetch_full_content {
+                    subscription.fetch_full_content = fetch_full_content;
+                }
+                if let Some(selector) = content_selector {
+                    subscription.content_selector = selector;
+                }
+                if let Some(download_enclosures) = download_enclosures {
+                    subscription.download_enclosures = download_enclosures;
+                }
+                if let Some(keep_downloads) = keep_downloads {
+                    subscription.keep_downloads = keep_downloads;
+                }
+                if let Some(credentials) = credentials {
+                    subscription.credentials = credentials;
+                }
+            })
+            .await?;
 
             if form.add_category != form.remove_category {
                 if let Some(category) = form.add_category.take() {
@@ -152,6 +342,7 @@ async fn edit(
         }
         "unsubscribe" => {
             db.remove_subscription(form.id.0).await?;
+            data.events.send(Event::SubscriptionRemoved(form.id.0));
         }
         _ => return Ok(HttpResponse::BadRequest().body("Bad value for ac")),
     }
@@ -159,6 +350,77 @@ async fn edit(
     Ok(HttpResponse::Ok().body("OK"))
 }
 
+fn parse_flag(value: &str) -> Option<bool> {
+    match value {
+        "1" | "true" => Some(true),
+        "0" | "false" => Some(false),
+        _ => None,
+    }
+}
+
+/// Credentials for private feeds.
+///
+/// They are only read from the request body to keep them out of access logs.
+/// Empty values are ignored, so sending only empty values clears credentials.
+#[derive(Default, Deserialize)]
+struct CredentialsForm {
+    username: Option<String>,
+    password: Option<String>,
+    token: Option<String>,
+    cookie: Option<String>,
+    /// One `Name: value` header per line.
+    headers: Option<String>,
+}
+
+impl CredentialsForm {
+    fn is_set(&self) -> bool {
+        self.username.is_some()
+            || self.password.is_some()
+            || self.token.is_some()
+            || self.cookie.is_some()
+            || self.headers.is_some()
+    }
+
+    fn into_credentials(self) -> Result<Credentials, &'static str> {
+        let non_empty = |value: Option<String>| value.filter(|v| !v.is_empty());
+
+        let auth = match (
+            non_empty(self.username),
+            non_empty(self.token),
+            non_empty(self.cookie),
+        ) {
+            (Some(username), None, None) => Some(Auth::Basic {
+                username,
+                password: non_empty(self.password),
+            }),
+            (None, Some(token), None) => Some(Auth::Bearer(token)),
+            (None, None, Some(cookie)) => Some(Auth::Cookie(cookie)),
+            (None, None, None) => None,
+            _ => return Err("Only one of username, token and cookie can be set."),
+        };
+
+        let mut credentials = Credentials {
+            auth,
+            headers: Vec::new(),
+        };
+
+        for line in self.headers.as_deref().unwrap_or("").lines() {
+            if !line.trim().is_empty() {
+                credentials.add_header(line)?;
+            }
+        }
+
+        Ok(credentials)
+    }
+}
+
+impl fmt::Debug for CredentialsForm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("CredentialsForm { .. }")
+    }
+}
+
+
 pub const SUBSCRIPTION_ID_PREFIX: &str = "feed/";
 
 /// A subscription is a feed.
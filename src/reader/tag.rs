@@ -0,0 +1,105 @@
+use actix_web::dev::HttpServiceFactory;
+use actix_web::{web, HttpResponse};
+use serde::{Deserialize, Serialize};
+
+use super::saved_search::SavedSearchId;
+use super::stream::StreamId;
+use super::subscription::LabelId;
+use crate::prelude::*;
+
+pub fn service() -> impl HttpServiceFactory {
+    web::resource("/tag/list").route(web::get().to(list))
+}
+
+
+#[derive(Debug, Serialize)]
+struct ListResponse<'a> {
+    tags: &'a Vec<ListResponseItem>,
+}
+
+#[derive(Debug, Serialize)]
+struct ListResponseItem {
+    id: StreamId,
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    kind: Option<&'static str>,
+}
+
+async fn list(data: web::Data<AppData>) -> actix_web::Result<HttpResponse> {
+    let mut db = data.db.clone();
+
+    let categories = db.get_categories().await?;
+    let item_labels = db.get_item_label_names().await?;
+    let saved_searches = db.get_saved_searches().await?;
+
+    // Labels are either categories or added to items by rules and users
+    let mut label_names: Vec<_> = categories.into_iter().map(|c| c.name).collect();
+    label_names.extend(item_labels);
+    label_names.sort();
+    label_names.dedup();
+
+    let states = vec![
+        StreamId::Starred,
+        StreamId::Read,
+        StreamId::Unread,
+        StreamId::RecentlyRead,
+    ];
+    let labels = label_names
+        .into_iter()
+        .map(|name| StreamId::UserLabel(LabelId(name)));
+    let searches = saved_searches
+        .into_iter()
+        .map(|saved_search| StreamId::SavedSearch(SavedSearchId(saved_search.name)));
+
+    let tags = states
+        .into_iter()
+        .map(|id| ListResponseItem { id, kind: None })
+        .chain(labels.map(|id| ListResponseItem {
+            id,
+            kind: Some("folder"),
+        }))
+        .chain(searches.map(|id| ListResponseItem {
+            id,
+            kind: Some("tag"),
+        }))
+        .collect();
+
+    Ok(HttpResponse::Ok().json(ListResponse {
+        tags: &tags,
+    }))
+}
+
+pub const TAG_ID_PREFIX: &str = "user/-/tag/";
+
+/// A tag is a category given to items by their feed.
+///
+/// Tags are matched case-insensitively.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Hash, Eq, PartialEq)]
+#[serde(into = "String", try_from = "String")]
+pub struct TagId(pub String);
+
+impl std::convert::Into<String> for TagId {
+    fn into(self) -> String {
+        format!("{}{}", TAG_ID_PREFIX, self.0)
+    }
+}
+
+impl std::convert::TryFrom<String> for TagId {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Self::try_from(value.as_str())
+    }
+}
+
+impl<'a> std::convert::TryFrom<&'a str> for TagId {
+    type Error = String;
+
+    fn try_from(value: &'a str) -> Result<Self, Self::Error> {
+        if value.starts_with(TAG_ID_PREFIX) && value.len() > TAG_ID_PREFIX.len() {
+            Ok(Self(value[TAG_ID_PREFIX.len()..].to_owned()))
+        } else {
+            Err(format!("Invalid tag ID: {}", value))
+        }
+    }
+}
@@ -0,0 +1,339 @@
+use reqwest::Url;
+
+use crate::html;
+
+/// Hosts using `/@name` URLs for profiles, which aren't Mastodon instances.
+///
+/// Subdomains are included.
+const NON_MASTODON_HOSTS: &[&str] = &[
+    "instagram.com",
+    "pinterest.com",
+    "substack.com",
+    "threads.net",
+    "tiktok.com",
+    "vimeo.com",
+];
+
+/// Top level paths of GitHub which aren't users or organizations.
+const GITHUB_RESERVED_PATHS: &[&str] = &[
+    "about",
+    "apps",
+    "codespaces",
+    "collections",
+    "dashboard",
+    "enterprise",
+    "events",
+    "explore",
+    "features",
+    "issues",
+    "login",
+    "marketplace",
+    "new",
+    "notifications",
+    "orgs",
+    "pricing",
+    "pulls",
+    "search",
+    "security",
+    "settings",
+    "sponsors",
+    "stars",
+    "topics",
+    "trending",
+];
+
+/// Rewrites the URL of a well known kind of web page to its feed URL.
+///
+/// Resolvers work offline: they only look at the URL.
+pub struct Resolver {
+    pub name: &'static str,
+    /// Hosts the resolver applies to.
+    ///
+    /// If empty, the resolver applies to hosts no other resolver knows.
+    pub hosts: &'static [&'static str],
+    pub resolve: fn(&Url, &[&str]) -> Option<String>,
+}
+
+/// Built-in resolvers, tried in order.
+pub const RESOLVERS: &[Resolver] = &[
+    Resolver {
+        name: "YouTube",
+        hosts: &["youtube.com", "www.youtube.com", "m.youtube.com"],
+        resolve: youtube,
+    },
+    Resolver {
+        name: "GitHub",
+        hosts: &["github.com", "www.github.com"],
+        resolve: github,
+    },
+    Resolver {
+        name: "Reddit",
+        hosts: &["reddit.com", "www.reddit.com", "old.reddit.com"],
+        resolve: reddit,
+    },
+    Resolver {
+        name: "Medium",
+        hosts: &["medium.com"],
+        resolve: medium,
+    },
+    Resolver {
+        name: "Mastodon",
+        hosts: &[],
+        resolve: mastodon,
+    },
+];
+
+/// Get the feed URL of `url` if it is a known kind of web page.
+pub fn resolve(url: &str) -> Option<String> {
+    resolve_with(RESOLVERS, url)
+}
+
+/// Get the feed URL of the web page at `url`, using its canonical URL.
+///
+/// This resolves pages whose URL alone isn't enough, e.g. YouTube handles
+/// (`/@name`) have their channel as canonical URL.
+pub fn resolve_page(url: &str, page: &str) -> Option<String> {
+    let canonical_url = html::find_canonical_url(page, url)?;
+    if canonical_url == url {
+        return None;
+    }
+
+    resolve(&canonical_url)
+}
+
+pub fn resolve_with(resolvers: &[Resolver], url: &str) -> Option<String> {
+    // Pasted URLs often lack the scheme
+    let url = Url::parse(url)
+        .or_else(|_| Url::parse(&format!("https://{}", url)))
+        .ok()?;
+
+    let host = url.host_str()?;
+    let segments: Vec<&str> = url
+        .path_segments()
+        .map(|segments| segments.filter(|s| !s.is_empty()).collect())
+        .unwrap_or_default();
+
+    let known_host = resolvers.iter().any(|r| r.hosts.contains(&host));
+
+    resolvers
+        .iter()
+        .filter(|r| {
+            if known_host {
+                r.hosts.contains(&host)
+            } else {
+                r.hosts.is_empty()
+            }
+        })
+        .find_map(|r| {
+            let feed_url = (r.resolve)(&url, &segments)?;
+            log::debug!("{} resolver: {} is {}", r.name, url, feed_url);
+            Some(feed_url)
+        })
+}
+
+/// Channels, users and playlists.
+///
+/// Handles (`/@name`) can't be resolved offline, see `resolve_page`.
+fn youtube(url: &Url, segments: &[&str]) -> Option<String> {
+    const BASE: &str = "https://www.youtube.com/feeds/videos.xml";
+
+    match segments {
+        ["channel", id, ..] => Some(format!("{}?channel_id={}", BASE, id)),
+        ["user", name, ..] => Some(format!("{}?user={}", BASE, name)),
+        ["playlist"] => url
+            .query_pairs()
+            .find(|(k, _)| k == "list")
+            .map(|(_, id)| format!("{}?playlist_id={}", BASE, id)),
+        _ => None,
+    }
+}
+
+/// Repository releases and tags, or a user's activity.
+fn github(_: &Url, segments: &[&str]) -> Option<String> {
+    const BASE: &str = "https://github.com";
+
+    // User and organization names can't contain dots, repository names can
+    match segments.first() {
+        Some(owner) if !owner.contains('.') && !GITHUB_RESERVED_PATHS.contains(owner) => (),
+        _ => return None,
+    }
+
+    match segments {
+        [owner] => Some(format!("{}/{}.atom", BASE, owner)),
+        [owner, repo, "tags", ..] => Some(format!("{}/{}/{}/tags.atom", BASE, owner, repo)),
+        [owner, repo] | [owner, repo, "releases", ..] if !repo.ends_with(".atom") => {
+            Some(format!("{}/{}/{}/releases.atom", BASE, owner, repo))
+        }
+        _ => None,
+    }
+}
+
+/// Subreddits and users.
+fn reddit(_: &Url, segments: &[&str]) -> Option<String> {
+    const BASE: &str = "https://www.reddit.com";
+
+    match segments {
+        ["r", name, ..] => Some(format!("{}/r/{}/.rss", BASE, name)),
+        ["u", name, ..] | ["user", name, ..] => Some(format!("{}/user/{}/.rss", BASE, name)),
+        _ => None,
+    }
+}
+
+/// User profiles.
+fn medium(_: &Url, segments: &[&str]) -> Option<String> {
+    match segments {
+        [user] if user.starts_with('@') => Some(format!("https://medium.com/feed/{}", user)),
+        _ => None,
+    }
+}
+
+/// Accounts, on any instance.
+///
+/// This is a guess, so subscribing falls back to the page if it is wrong.
+fn mastodon(url: &Url, segments: &[&str]) -> Option<String> {
+    let host = url.host_str()?;
+    let is_excluded = NON_MASTODON_HOSTS
+        .iter()
+        .any(|excluded| host == *excluded || host.ends_with(&format!(".{}", excluded)));
+    if is_excluded {
+        return None;
+    }
+
+    match segments {
+        [user] if user.len() > 1 && user.starts_with('@') && !user.contains('.') => Some(format!(
+            "{}/{}.rss",
+            url.origin().ascii_serialization(),
+            user
+        )),
+        _ => None,
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve_known_pages() {
+        let cases = [
+            (
+                "https://www.youtube.com/channel/UC123abc",
+                "https://www.youtube.com/feeds/videos.xml?channel_id=UC123abc",
+            ),
+            (
+                "youtube.com/channel/UC123abc/videos",
+                "https://www.youtube.com/feeds/videos.xml?channel_id=UC123abc",
+            ),
+            (
+                "https://www.youtube.com/user/someone",
+                "https://www.youtube.com/feeds/videos.xml?user=someone",
+            ),
+            (
+                "https://www.youtube.com/playlist?list=PL42",
+                "https://www.youtube.com/feeds/videos.xml?playlist_id=PL42",
+            ),
+            (
+                "https://github.com/rust-lang/rust",
+                "https://github.com/rust-lang/rust/releases.atom",
+            ),
+            (
+                "https://github.com/rust-lang/rust/releases/tag/1.0.0",
+                "https://github.com/rust-lang/rust/releases.atom",
+            ),
+            (
+                "https://github.com/rust-lang/rust/tags",
+                "https://github.com/rust-lang/rust/tags.atom",
+            ),
+            (
+                "https://github.com/socketio/socket.io",
+                "https://github.com/socketio/socket.io/releases.atom",
+            ),
+            (
+                "https://github.com/vuejs/vue.js/tags",
+                "https://github.com/vuejs/vue.js/tags.atom",
+            ),
+            (
+                "https://github.com/someone",
+                "https://github.com/someone.atom",
+            ),
+            (
+                "https://www.reddit.com/r/rust/",
+                "https://www.reddit.com/r/rust/.rss",
+            ),
+            (
+                "old.reddit.com/r/rust/top",
+                "https://www.reddit.com/r/rust/.rss",
+            ),
+            (
+                "https://reddit.com/u/someone",
+                "https://www.reddit.com/user/someone/.rss",
+            ),
+            (
+                "https://medium.com/@someone",
+                "https://medium.com/feed/@someone",
+            ),
+            (
+                "https://mastodon.social/@someone",
+                "https://mastodon.social/@someone.rss",
+            ),
+        ];
+
+        for (url, expected) in &cases {
+            assert_eq!(resolve(url).as_deref(), Some(*expected), "{}", url);
+        }
+    }
+
+    #[test]
+    fn ignore_other_pages() {
+        let urls = [
+            "https://www.youtube.com/@someone",
+            "https://www.youtube.com/watch?v=abc",
+            "https://github.com/rust-lang/rust/releases.atom",
+            "https://github.com/rust-lang/rust/issues/1",
+            "https://github.com/someone.atom",
+            "https://github.com/settings",
+            "https://github.com/settings/profile",
+            "https://github.com/orgs/rust-lang/repositories",
+            "https://github.com/topics/rust",
+            "https://github.com/explore",
+            "https://www.reddit.com/",
+            "https://example.com/blog/",
+            "https://example.com/@someone.rss",
+            "https://example.com/feed.xml",
+            "https://www.tiktok.com/@someone",
+            "https://www.threads.net/@someone",
+            "https://substack.com/@someone",
+            "https://someone.substack.com/@someone",
+        ];
+
+        for url in &urls {
+            assert_eq!(resolve(url), None, "{}", url);
+        }
+    }
+
+    #[test]
+    fn resolve_pages() {
+        let page = r#"<html><head>
+            <link rel="canonical" href="https://www.youtube.com/channel/UC123abc">
+        </head></html>"#;
+        assert_eq!(
+            resolve_page("https://www.youtube.com/@someone", page).as_deref(),
+            Some("https://www.youtube.com/feeds/videos.xml?channel_id=UC123abc")
+        );
+
+        let page = r#"<html><head>
+            <meta property="og:url" content="https://www.youtube.com/channel/UC123abc">
+        </head></html>"#;
+        assert_eq!(
+            resolve_page("https://www.youtube.com/@someone", page).as_deref(),
+            Some("https://www.youtube.com/feeds/videos.xml?channel_id=UC123abc")
+        );
+
+        let page = r#"<html><head>
+            <link rel="canonical" href="https://example.com/blog/">
+        </head></html>"#;
+        assert_eq!(resolve_page("https://example.com/blog/", page), None);
+        assert_eq!(resolve_page("https://example.com/", "<html></html>"), None);
+    }
+}
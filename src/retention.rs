@@ -0,0 +1,55 @@
+use crate::db::models::{Category, Subscription};
+use crate::prelude::*;
+
+/// Rules deciding which items get purged.
+///
+/// Starred items and items kept unread are always kept.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RetentionPolicy {
+    /// Delete items read more than this many days ago.
+    pub read_days: Option<u32>,
+    /// Keep at most this many items per subscription.
+    pub max_items: Option<u32>,
+}
+
+impl RetentionPolicy {
+    pub fn from_config(cfg: &Config) -> Self {
+        RetentionPolicy {
+            read_days: cfg.retention_read_days,
+            max_items: cfg.retention_max_items,
+        }
+    }
+
+    /// Get the policy of `subscription`, using `self` as the default.
+    ///
+    /// Values set on the subscription have priority over the ones set on
+    /// its categories. If several categories set a value, the most lenient
+    /// one is used. A value of 0 means there is no limit.
+    pub fn for_subscription(&self, subscription: &Subscription, categories: &[Category]) -> Self {
+        let read_days: Vec<_> = categories.iter().map(|c| c.keep_read_days).collect();
+        let max_items: Vec<_> = categories.iter().map(|c| c.keep_max_items).collect();
+
+        RetentionPolicy {
+            read_days: resolve(subscription.keep_read_days, &read_days, self.read_days),
+            max_items: resolve(subscription.keep_max_items, &max_items, self.max_items),
+        }
+    }
+}
+
+fn resolve(own: Option<i32>, categories: &[Option<i32>], default: Option<u32>) -> Option<u32> {
+    let overridden = own.or_else(|| {
+        let values = categories.iter().flatten();
+
+        if values.clone().any(|v| *v <= 0) {
+            Some(0)
+        } else {
+            values.max().copied()
+        }
+    });
+
+    match overridden {
+        Some(value) if value <= 0 => None,
+        Some(value) => Some(value as u32),
+        None => default,
+    }
+}
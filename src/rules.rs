@@ -0,0 +1,420 @@
+//! User-defined filter rules, applied to items when they are stored.
+//!
+//! A rule matches a keyword (case insensitive) or a regex against a field
+//! of the items in its scope: a subscription, a category or everything.
+
+use regex::{Regex, RegexBuilder};
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use crate::db::models::{Category, Item, NewItemLabel, NewRule, Rule};
+use crate::html;
+use crate::prelude::*;
+
+/// Item field a rule matches against.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Field {
+    Title,
+    /// Text of the content, without HTML markup.
+    Content,
+    Author,
+    Url,
+    /// Categories of the entry in its feed.
+    Category,
+}
+
+impl FromStr for Field {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "title" => Field::Title,
+            "content" => Field::Content,
+            "author" => Field::Author,
+            "url" => Field::Url,
+            "category" => Field::Category,
+            _ => return Err(format!("Invalid rule field: {}", s)),
+        })
+    }
+}
+
+/// What to do with matching items.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    MarkRead,
+    Star,
+    Label(String),
+    /// Don't store the item.
+    Drop,
+}
+
+impl Action {
+    fn parse(action: &str, label: Option<&str>) -> Result<Self, String> {
+        Ok(match (action, label) {
+            ("read", _) => Action::MarkRead,
+            ("star", _) => Action::Star,
+            ("label", Some(label)) if !label.is_empty() => Action::Label(label.to_owned()),
+            ("label", _) => return Err("Missing label for rule action".to_owned()),
+            ("drop", _) => Action::Drop,
+            _ => return Err(format!("Invalid rule action: {}", action)),
+        })
+    }
+}
+
+#[derive(Debug)]
+enum Pattern {
+    /// Lowercase keyword.
+    Keyword(String),
+    Regex(Regex),
+}
+
+impl Pattern {
+    fn new(pattern: &str, is_regex: bool) -> Result<Self, String> {
+        if pattern.is_empty() {
+            return Err("Empty rule pattern".to_owned());
+        }
+
+        if is_regex {
+            RegexBuilder::new(pattern)
+                .case_insensitive(true)
+                .build()
+                .map(Pattern::Regex)
+                .map_err(|e| e.to_string())
+        } else {
+            Ok(Pattern::Keyword(pattern.to_lowercase()))
+        }
+    }
+
+    fn is_match(&self, value: &str) -> bool {
+        match self {
+            Pattern::Keyword(keyword) => value.to_lowercase().contains(keyword.as_str()),
+            Pattern::Regex(regex) => regex.is_match(value),
+        }
+    }
+}
+
+/// Check a new rule can be used.
+pub fn validate(rule: &NewRule) -> Result<(), String> {
+    Field::from_str(&rule.field)?;
+    Pattern::new(&rule.pattern, rule.is_regex)?;
+    Action::parse(&rule.action, rule.label.as_deref())?;
+
+    if rule.subscription_id.is_some() && rule.category.is_some() {
+        return Err("A rule can't be scoped to both a feed and a label".to_owned());
+    }
+
+    Ok(())
+}
+
+/// Item fields rules match against.
+#[derive(Debug, Clone, Copy)]
+pub struct Target<'a> {
+    pub title: &'a str,
+    /// HTML content.
+    pub content: &'a str,
+    pub author: Option<&'a str>,
+    pub url: &'a str,
+    pub categories: &'a [String],
+}
+
+impl<'a> From<&'a Item> for Target<'a> {
+    /// Categories are stored separately, as tags.
+    fn from(item: &'a Item) -> Self {
+        Target {
+            title: &item.title,
+            content: &item.content,
+            author: item.author.as_deref(),
+            url: &item.url,
+            categories: &[],
+        }
+    }
+}
+
+/// Result of applying rules to an item.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Outcome {
+    pub drop: bool,
+    pub read: bool,
+    pub star: bool,
+    pub labels: Vec<String>,
+}
+
+impl Outcome {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+#[derive(Debug)]
+struct CompiledRule {
+    name: String,
+    field: Field,
+    pattern: Pattern,
+    action: Action,
+    subscription_id: Option<db::Id>,
+    category: Option<String>,
+}
+
+impl CompiledRule {
+    fn new(rule: Rule) -> Result<Self, String> {
+        Ok(Self {
+            field: Field::from_str(&rule.field)?,
+            pattern: Pattern::new(&rule.pattern, rule.is_regex)?,
+            action: Action::parse(&rule.action, rule.label.as_deref())?,
+            name: rule.name,
+            subscription_id: rule.subscription_id,
+            category: rule.category,
+        })
+    }
+
+    fn applies_to(&self, subscription_id: db::Id, categories: &[Category]) -> bool {
+        match (&self.subscription_id, &self.category) {
+            (Some(id), _) => *id == subscription_id,
+            (None, Some(name)) => categories.iter().any(|c| &c.name == name),
+            (None, None) => true,
+        }
+    }
+
+    fn matches(&self, target: &Target, text: &str) -> bool {
+        match self.field {
+            Field::Title => self.pattern.is_match(target.title),
+            Field::Content => self.pattern.is_match(text),
+            Field::Author => target.author.map_or(false, |a| self.pattern.is_match(a)),
+            Field::Url => self.pattern.is_match(target.url),
+            Field::Category => target.categories.iter().any(|c| self.pattern.is_match(c)),
+        }
+    }
+}
+
+/// Rules ready to be applied.
+#[derive(Debug)]
+pub struct RuleSet {
+    rules: Vec<CompiledRule>,
+}
+
+impl RuleSet {
+    /// Compile `rules`, skipping invalid ones.
+    pub fn new(rules: Vec<Rule>) -> Self {
+        let rules = rules
+            .into_iter()
+            .filter_map(|rule| {
+                let name = rule.name.clone();
+
+                CompiledRule::new(rule)
+                    .map_err(|e| log::error!("Ignoring rule {}: {}", name, e))
+                    .ok()
+            })
+            .collect();
+
+        Self { rules }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Apply the rules in scope for an item of `subscription_id`.
+    pub fn apply(
+        &self,
+        subscription_id: db::Id,
+        categories: &[Category],
+        target: &Target,
+    ) -> Outcome {
+        let rules: Vec<_> = self
+            .rules
+            .iter()
+            .filter(|rule| rule.applies_to(subscription_id, categories))
+            .collect();
+
+        // Only strip the markup when needed
+        let text = if rules.iter().any(|rule| rule.field == Field::Content) {
+            html::text_content(target.content)
+        } else {
+            String::new()
+        };
+
+        let mut outcome = Outcome::default();
+
+        for rule in rules {
+            if !rule.matches(target, &text) {
+                continue;
+            }
+
+            log::trace!("Rule {} matches: {}", rule.name, target.title);
+
+            match &rule.action {
+                Action::MarkRead => outcome.read = true,
+                Action::Star => outcome.star = true,
+                Action::Label(label) => {
+                    if !outcome.labels.contains(label) {
+                        outcome.labels.push(label.clone());
+                    }
+                }
+                Action::Drop => outcome.drop = true,
+            }
+        }
+
+        outcome
+    }
+}
+
+/// Apply the rules to all stored items.
+///
+/// Categories are the items' tags. Starred items and items kept unread are
+/// never dropped, as retention always keeps them.
+/// Returns the IDs of the affected items, dropped ones included.
+pub async fn reapply(db: &mut db::Helper) -> Result<Vec<db::Id>, db::Error> {
+    let rules = RuleSet::new(db.get_rules().await?);
+    if rules.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut read = Vec::new();
+    let mut starred = Vec::new();
+    let mut labels = Vec::new();
+    let mut dropped = Vec::new();
+    let mut affected = Vec::new();
+
+    for subscription in db.get_subscriptions().await? {
+        let categories = db.get_subscription_categories(subscription.id).await?;
+
+        let mut tags: HashMap<db::Id, Vec<String>> = HashMap::new();
+        for tag in db.get_subscription_items_tags(subscription.id).await? {
+            tags.entry(tag.item_id).or_default().push(tag.name);
+        }
+
+        for item in db.get_subscription_items(subscription.id).await? {
+            let mut target = Target::from(&item);
+            if let Some(item_tags) = tags.get(&item.id) {
+                target.categories = item_tags;
+            }
+
+            let mut outcome = rules.apply(subscription.id, &categories, &target);
+            if item.is_starred || item.is_kept_unread {
+                outcome.drop = false;
+            }
+            if outcome.is_empty() {
+                continue;
+            }
+
+            affected.push(item.id);
+
+            if outcome.drop {
+                dropped.push(item.id);
+                continue;
+            }
+
+            if outcome.read && !item.is_read && !item.is_kept_unread {
+                read.push(item.id);
+            }
+            if outcome.star && !item.is_starred {
+                starred.push(item.id);
+            }
+            labels.extend(outcome.labels.into_iter().map(|name| NewItemLabel {
+                item_id: item.id,
+                name,
+            }));
+        }
+    }
+
+    db.update_items_state(read, Some(true), None).await?;
+    db.update_items_state(starred, None, Some(true)).await?;
+    db.add_item_labels(labels).await?;
+    db.delete_items(dropped).await?;
+
+    Ok(affected)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(field: &str, pattern: &str, is_regex: bool, action: &str) -> Rule {
+        Rule {
+            id: db::Id::from_raw(1),
+            name: format!("{} {}", field, pattern),
+            field: field.to_owned(),
+            pattern: pattern.to_owned(),
+            is_regex,
+            subscription_id: None,
+            category: None,
+            action: action.to_owned(),
+            label: Some("product".to_owned()),
+        }
+    }
+
+    fn target<'a>(title: &'a str, content: &'a str, categories: &'a [String]) -> Target<'a> {
+        Target {
+            title,
+            content,
+            author: None,
+            url: "https://example.com/post",
+            categories,
+        }
+    }
+
+    #[test]
+    fn apply_actions() {
+        let rules = RuleSet::new(vec![
+            rule("title", "sponsored", false, "drop"),
+            rule("content", r"\bfreader\b", true, "star"),
+            rule("content", "freader", false, "label"),
+            rule("category", "ads", false, "read"),
+        ]);
+        let id = db::Id::from_raw(1);
+        let categories = vec!["Ads".to_owned()];
+
+        let outcome = rules.apply(id, &[], &target("[SPONSORED] Buy now", "", &[]));
+        assert!(outcome.drop);
+
+        let outcome = rules.apply(id, &[], &target("News", "<b>freader</b> 1.0", &[]));
+        assert_eq!(
+            outcome,
+            Outcome {
+                star: true,
+                labels: vec!["product".to_owned()],
+                ..Default::default()
+            }
+        );
+
+        let outcome = rules.apply(id, &[], &target("News", "<a href=\"freader\">x</a>", &[]));
+        assert!(outcome.is_empty());
+
+        let outcome = rules.apply(id, &[], &target("News", "", &categories));
+        assert!(outcome.read);
+    }
+
+    #[test]
+    fn apply_in_scope() {
+        let mut feed_rule = rule("title", "rust", false, "star");
+        feed_rule.subscription_id = Some(db::Id::from_raw(1));
+        let mut label_rule = rule("title", "rust", false, "read");
+        label_rule.category = Some("Blogs".to_owned());
+        let rules = RuleSet::new(vec![feed_rule, label_rule]);
+
+        let blogs = Category {
+            id: db::Id::from_raw(1),
+            name: "Blogs".to_owned(),
+            keep_read_days: None,
+            keep_max_items: None,
+        };
+        let item = target("Rust 1.47", "", &[]);
+
+        let outcome = rules.apply(db::Id::from_raw(1), &[], &item);
+        assert!(outcome.star && !outcome.read);
+
+        let outcome = rules.apply(db::Id::from_raw(2), &[blogs], &item);
+        assert!(!outcome.star && outcome.read);
+    }
+
+    #[test]
+    fn skip_invalid_rules() {
+        let rules = RuleSet::new(vec![
+            rule("title", "(", true, "drop"),
+            rule("summary", "x", false, "drop"),
+        ]);
+
+        assert!(rules.is_empty());
+    }
+}
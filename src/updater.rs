@@ -1,19 +1,31 @@
 use actix::prelude::*;
 use rand::Rng;
 
-use crate::db::models::Subscription;
+use crate::db::models::{Operation, Subscription};
+use crate::events::{Event, Events};
 use crate::feed_manager::FeedManager;
 use crate::prelude::*;
+use crate::retention::RetentionPolicy;
 
-/// Actor that periodically refreshes subscriptions.
+/// Actor that periodically refreshes subscriptions and purges old items and
+/// operations.
 pub struct Updater {
     db: db::Helper,
+    events: Events,
     feed_manager: FeedManager,
+    retention: RetentionPolicy,
+    undo_window: std::time::Duration,
 }
 
 impl Updater {
-    pub fn new(db: db::Helper, feed_manager: FeedManager) -> Self {
-        Updater { db, feed_manager }
+    pub fn new(cfg: &Config, db: db::Helper, events: Events, feed_manager: FeedManager) -> Self {
+        Updater {
+            db,
+            events,
+            feed_manager,
+            retention: RetentionPolicy::from_config(cfg),
+            undo_window: cfg.undo_window,
+        }
     }
 
     /// Generate a DateTime one hour from (with a small random offset).
@@ -37,6 +49,10 @@ impl Updater {
     fn refresh_outdated(&mut self, ctx: &mut <Self as Actor>::Context) {
         ctx.notify(RefreshOutdated);
     }
+
+    fn purge(&mut self, ctx: &mut <Self as Actor>::Context) {
+        ctx.notify(Purge);
+    }
 }
 
 impl Actor for Updater {
@@ -51,6 +67,9 @@ impl Actor for Updater {
             std::time::Duration::from_secs(5 * 60),
             Self::refresh_outdated,
         );
+
+        // Purge old items every 6 hours
+        ctx.run_interval(std::time::Duration::from_secs(6 * 60 * 60), Self::purge);
     }
 }
 
@@ -103,3 +122,43 @@ impl Handler<RefreshOutdated> for Updater {
         }))
     }
 }
+
+
+/// Delete items according to retention policies, and operations which can't
+/// be reverted anymore.
+struct Purge;
+
+impl Message for Purge {
+    type Result = Result<(), ()>;
+}
+
+impl Handler<Purge> for Updater {
+    type Result = ResponseActFuture<Self, <Purge as Message>::Result>;
+
+    fn handle(&mut self, _: Purge, _: &mut Self::Context) -> Self::Result {
+        let mut db = self.db.clone();
+        let events = self.events.clone();
+        let retention = self.retention;
+        let undo_window = self.undo_window;
+
+        Box::pin(actix::fut::wrap_future(async move {
+            log::debug!("Purging old items");
+
+            let ids = db.purge_items(retention, false).await.map_err(|e| {
+                log::error!("Could not purge items: {}", e);
+            })?;
+
+            if !ids.is_empty() {
+                log::info!("Purged {} items", ids.len());
+                events.send(Event::ItemsChanged(ids));
+            }
+
+            let min_date = Operation::min_date(undo_window);
+            db.purge_operations(min_date).await.map_err(|e| {
+                log::error!("Could not purge operations: {}", e);
+            })?;
+
+            Ok(())
+        }))
+    }
+}
@@ -0,0 +1,167 @@
+//! `xml:base` of feed entries, which `feed_rs` doesn't keep.
+//!
+//! Relative URLs in the content of an entry are relative to the
+//! `xml:base` of the content element, inherited from its ancestors.
+
+use xml::name::OwnedName;
+use xml::reader::{EventReader, XmlEvent};
+
+use crate::utils::make_url_absolute;
+
+/// Namespace of `media:content`, which isn't the content of an entry.
+const MEDIA_RSS_NAMESPACE: &str = "http://search.yahoo.com/mrss/";
+
+/// The base URL of the content of each entry of `feed`, in document order.
+///
+/// It is `None` when no `xml:base` applies to the content. The result is
+/// empty if `feed` isn't XML.
+pub fn content_bases(feed: &[u8], feed_url: &str) -> Vec<Option<String>> {
+    // Base of each open element
+    let mut bases: Vec<Option<String>> = Vec::new();
+    let mut entry: Option<EntryBases> = None;
+    let mut result = Vec::new();
+
+    for event in EventReader::new(feed) {
+        match event {
+            Ok(XmlEvent::StartElement {
+                name, attributes, ..
+            }) => {
+                let parent = bases.last().cloned().flatten();
+                let declared = attributes
+                    .iter()
+                    .find(|attribute| is_xml_base(&attribute.name))
+                    .map(|attribute| attribute.value.trim());
+                let base = match declared {
+                    Some(declared) => {
+                        make_url_absolute(declared, parent.as_deref().unwrap_or(feed_url))
+                            .ok()
+                            .or(parent)
+                    }
+                    None => parent,
+                };
+
+                let element = name.local_name.as_str();
+                if entry.is_none() && (element == "entry" || element == "item") {
+                    entry = Some(EntryBases {
+                        depth: bases.len(),
+                        entry: base.clone(),
+                        content: None,
+                        summary: None,
+                    });
+                } else if let Some(entry) = &mut entry {
+                    match element {
+                        _ if name.namespace.as_deref() == Some(MEDIA_RSS_NAMESPACE) => {}
+                        "content" | "encoded" => {
+                            entry.content.get_or_insert(base.clone());
+                        }
+                        "summary" | "description" => {
+                            entry.summary.get_or_insert(base.clone());
+                        }
+                        _ => {}
+                    }
+                }
+
+                bases.push(base);
+            }
+            Ok(XmlEvent::EndElement { .. }) => {
+                bases.pop();
+
+                if matches!(&entry, Some(entry) if entry.depth == bases.len()) {
+                    if let Some(entry) = entry.take() {
+                        result.push(entry.content_base());
+                    }
+                }
+            }
+            Ok(_) => {}
+            Err(_) => break,
+        }
+    }
+
+    result
+}
+
+fn is_xml_base(name: &OwnedName) -> bool {
+    name.prefix.as_deref() == Some("xml") && name.local_name == "base"
+}
+
+/// Bases found in an entry.
+struct EntryBases {
+    /// Depth of the entry's element.
+    depth: usize,
+    entry: Option<String>,
+    content: Option<Option<String>>,
+    summary: Option<Option<String>>,
+}
+
+impl EntryBases {
+    /// The base of what is used as the item's content: the entry's content,
+    /// or its summary.
+    fn content_base(self) -> Option<String> {
+        self.content.or(self.summary).unwrap_or(self.entry)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FEED_URL: &str = "https://example.com/blog/feed.xml";
+
+    #[test]
+    fn atom_bases() {
+        let feed = r#"<?xml version="1.0" encoding="utf-8"?>
+            <feed xmlns="http://www.w3.org/2005/Atom" xml:base="https://example.com/blog/">
+              <entry>
+                <id>1</id>
+                <content type="html">&lt;img src="a.png"&gt;</content>
+              </entry>
+              <entry xml:base="posts/2/">
+                <id>2</id>
+                <content type="html" xml:base="images/">&lt;img src="b.png"&gt;</content>
+              </entry>
+              <entry xml:base="https://cdn.example.org/">
+                <id>3</id>
+                <summary>Summary</summary>
+              </entry>
+            </feed>"#;
+
+        assert_eq!(
+            content_bases(feed.as_bytes(), FEED_URL),
+            vec![
+                Some("https://example.com/blog/".to_owned()),
+                Some("https://example.com/blog/posts/2/images/".to_owned()),
+                Some("https://cdn.example.org/".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn relative_to_feed_url() {
+        let feed = r#"<feed xmlns="http://www.w3.org/2005/Atom">
+              <entry><id>1</id><content xml:base="/posts/1/">Content</content></entry>
+              <entry><id>2</id><content>Content</content></entry>
+            </feed>"#;
+
+        assert_eq!(
+            content_bases(feed.as_bytes(), FEED_URL),
+            vec![Some("https://example.com/posts/1/".to_owned()), None]
+        );
+    }
+
+    #[test]
+    fn rss_items() {
+        let feed = r#"<rss version="2.0"><channel>
+              <item><guid>1</guid><description>Description</description></item>
+            </channel></rss>"#;
+
+        assert_eq!(content_bases(feed.as_bytes(), FEED_URL), vec![None]);
+    }
+
+    #[test]
+    fn not_xml() {
+        let feed = br#"{"version": "https://jsonfeed.org/version/1"}"#;
+
+        assert!(content_bases(feed, FEED_URL).is_empty());
+    }
+}